@@ -0,0 +1,220 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use fake::{uuid::UUIDv4, Fake};
+use md5::Md5;
+use reqwest::{
+    header::{HeaderMap, WWW_AUTHENTICATE},
+    Method, Url,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// Basic credentials given as "user pass" or "user:pass" are encoded, encoded ones are kept
+pub fn basic_authorization(value: &str) -> String {
+    let Some(credentials) = strip_scheme(value, "Basic") else {
+        return value.to_string();
+    };
+    let credentials = match credentials.split_once(char::is_whitespace) {
+        Some((username, password)) => format!("{}:{}", username, password.trim()),
+        None if credentials.contains(':') => credentials.to_string(),
+        None => return value.to_string(),
+    };
+    format!("Basic {}", STANDARD.encode(credentials))
+}
+
+// Credentials to answer a digest challenge with, e.g. "Digest user pass"
+pub struct DigestAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl DigestAuth {
+    // None for other schemes and for a computed digest header, which is sent as is
+    pub fn parse(value: &str) -> Option<Self> {
+        let credentials = strip_scheme(value, "Digest")?;
+        if credentials.contains("username=") {
+            return None;
+        }
+        let (username, password) = credentials
+            .split_once(char::is_whitespace)
+            .or_else(|| credentials.split_once(':'))
+            .unwrap_or((credentials, ""));
+        Some(DigestAuth {
+            username: username.to_string(),
+            password: password.trim().to_string(),
+        })
+    }
+
+    // The authorization header answering the digest challenge of a 401 response
+    pub fn authorization(
+        &self,
+        headers: &HeaderMap,
+        method: &Method,
+        url: &Url,
+        body: Option<&[u8]>,
+    ) -> anyhow::Result<Option<String>> {
+        let Some(challenge) = headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(|value| strip_scheme(value, "Digest"))
+        else {
+            return Ok(None);
+        };
+        let cnonce = UUIDv4.fake::<String>().replace('-', "");
+        self.answer(challenge, method, url, body, &cnonce).map(Some)
+    }
+
+    fn answer(
+        &self,
+        challenge: &str,
+        method: &Method,
+        url: &Url,
+        body: Option<&[u8]>,
+        cnonce: &str,
+    ) -> anyhow::Result<String> {
+        let params = challenge_params(challenge);
+        let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+
+        let algorithm = match param("algorithm") {
+            "" => "MD5",
+            algorithm => algorithm,
+        };
+        let hash: fn(&[u8]) -> String = match algorithm.to_ascii_uppercase().as_str() {
+            "MD5" | "MD5-SESS" => |data| format!("{:x}", Md5::digest(data)),
+            "SHA-256" | "SHA-256-SESS" => |data| format!("{:x}", Sha256::digest(data)),
+            _ => anyhow::bail!("Unsupported digest algorithm \"{}\"", algorithm),
+        };
+
+        // auth is preferred, auth-int also covers the body
+        let qops: Vec<&str> = param("qop").split(',').map(str::trim).collect();
+        let qop = ["auth", "auth-int"]
+            .into_iter()
+            .find(|qop| qops.contains(qop));
+
+        let realm = param("realm");
+        let nonce = param("nonce");
+        let nc = "00000001";
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        let mut ha1 = hash(format!("{}:{}:{}", self.username, realm, self.password).as_bytes());
+        if algorithm.to_ascii_uppercase().ends_with("-SESS") {
+            ha1 = hash(format!("{}:{}:{}", ha1, nonce, cnonce).as_bytes());
+        }
+        let ha2 = match qop {
+            Some("auth-int") => {
+                hash(format!("{}:{}:{}", method, uri, hash(body.unwrap_or_default())).as_bytes())
+            }
+            _ => hash(format!("{}:{}", method, uri).as_bytes()),
+        };
+        let response = match qop {
+            Some(qop) => {
+                hash(format!("{}:{}:{}:{}:{}:{}", ha1, nonce, nc, cnonce, qop, ha2).as_bytes())
+            }
+            None => hash(format!("{}:{}:{}", ha1, nonce, ha2).as_bytes()),
+        };
+
+        let mut authorization = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+            self.username, realm, nonce, uri, algorithm, response
+        );
+        if let Some(qop) = qop {
+            authorization.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+        }
+        if let Some(opaque) = params.get("opaque") {
+            authorization.push_str(&format!(", opaque=\"{}\"", opaque));
+        }
+        Ok(authorization)
+    }
+}
+
+// The credentials after a case insensitive scheme, e.g. "Basic dXNlcg=="
+fn strip_scheme<'a>(value: &'a str, scheme: &str) -> Option<&'a str> {
+    let (name, credentials) = value.trim().split_once(char::is_whitespace)?;
+    match name.eq_ignore_ascii_case(scheme) {
+        true => Some(credentials.trim()),
+        false => None,
+    }
+}
+
+// Parameters such as realm="test", qop="auth,auth-int", algorithm=MD5
+fn challenge_params(challenge: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut rest = challenge.trim();
+    while let Some((name, value)) = rest.split_once('=') {
+        let name = name
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let value = value.trim_start();
+        let (value, next) = match value.strip_prefix('"') {
+            // quoted values may contain commas
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+            }
+            None => {
+                let end = value.find(',').unwrap_or(value.len());
+                (value[..end].trim(), &value[end..])
+            }
+        };
+        params.insert(name, value.to_string());
+        rest = next;
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 7616 section 3.9.1
+    const CHALLENGE: &str = "realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+        nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+        opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn answer(algorithm: &str) -> String {
+        let auth = DigestAuth::parse("Digest Mufasa Circle of Life").unwrap();
+        let url = Url::parse("http://www.example.org/dir/index.html").unwrap();
+        let challenge = format!("{}, algorithm={}", CHALLENGE, algorithm);
+        auth.answer(&challenge, &Method::GET, &url, None, CNONCE)
+            .unwrap()
+    }
+
+    #[test]
+    fn rfc_7616_md5() {
+        assert_eq!(
+            answer("MD5"),
+            "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", uri=\"/dir/index.html\", \
+             algorithm=MD5, response=\"8ca523f5e9506fed4657c9700eebdbec\", qop=auth, \
+             nc=00000001, cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""
+        );
+    }
+
+    #[test]
+    fn rfc_7616_sha256() {
+        assert!(answer("SHA-256").contains(
+            "response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\""
+        ));
+    }
+
+    #[test]
+    fn parse_credentials() {
+        let auth = DigestAuth::parse("Digest Mufasa Circle of Life").unwrap();
+        assert_eq!(auth.username, "Mufasa");
+        assert_eq!(auth.password, "Circle of Life");
+        let auth = DigestAuth::parse("digest user:pass").unwrap();
+        assert_eq!(
+            (auth.username.as_str(), auth.password.as_str()),
+            ("user", "pass")
+        );
+        assert!(DigestAuth::parse("Digest username=\"user\", response=\"x\"").is_none());
+        assert!(DigestAuth::parse("Basic dXNlcg==").is_none());
+    }
+}
@@ -0,0 +1,444 @@
+use anyhow::Context as ErrorContext;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    blocking::{Body, Request},
+    header::{HeaderValue, AUTHORIZATION},
+};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+// Everything but the unreserved characters A-Z, a-z, 0-9, -, _, . and ~
+const URI_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+// Credentials and scope to sign a request with Signature Version 4
+pub struct AwsAuth {
+    // given in the request, otherwise looked up when signing
+    credentials: Option<AwsCredentials>,
+    // profile in the shared credentials file, set with @auth
+    profile: Option<String>,
+    region: Option<String>,
+    service: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+}
+
+impl AwsAuth {
+    // REST Client's shorthand, e.g. "AWS <access key> <secret key> token:<token> region:<region> service:<service>",
+    // None for other schemes and for a computed AWS4-HMAC-SHA256 header
+    pub fn parse(value: &str) -> Option<anyhow::Result<Self>> {
+        let mut parts = value.split_whitespace();
+        if parts.next() != Some("AWS") {
+            return None;
+        }
+        let (Some(access_key), Some(secret_key)) = (parts.next(), parts.next()) else {
+            return Some(Err(anyhow::anyhow!(
+                "Expected \"AWS <access key> <secret key>\" in the authorization header"
+            )));
+        };
+        let mut credentials = AwsCredentials {
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            session_token: None,
+        };
+        let mut auth = AwsAuth {
+            credentials: None,
+            profile: None,
+            region: None,
+            service: None,
+        };
+        for part in parts {
+            match part.split_once(':') {
+                Some(("token", token)) => credentials.session_token = Some(token.to_string()),
+                Some(("region", region)) => auth.region = Some(region.to_string()),
+                Some(("service", service)) => auth.service = Some(service.to_string()),
+                _ => {
+                    return Some(Err(anyhow::anyhow!(
+                    "Unknown AWS authorization option \"{}\", expected token, region or service",
+                    part
+                )))
+                }
+            }
+        }
+        auth.credentials = Some(credentials);
+        Some(Ok(auth))
+    }
+
+    // "@auth aws-sigv4 service=execute-api region=eu-west-1 profile=dev", credentials
+    // come from the environment variables or the shared credentials file
+    pub fn from_metadata(spec: &str) -> anyhow::Result<Option<Self>> {
+        let mut parts = spec.split_whitespace();
+        if parts.next() != Some("aws-sigv4") {
+            return Ok(None);
+        }
+        let mut options: BTreeMap<&str, &str> = BTreeMap::new();
+        for part in parts {
+            let (key, value) = part
+                .split_once('=')
+                .with_context(|| format!("Expected key=value, got \"{}\"", part))?;
+            match key {
+                "service" | "region" | "profile" => options.insert(key, value),
+                _ => anyhow::bail!(
+                    "Unknown option \"{}\", expected service, region or profile",
+                    key
+                ),
+            };
+        }
+
+        Ok(Some(AwsAuth {
+            credentials: None,
+            profile: options.get("profile").map(|profile| profile.to_string()),
+            region: options.get("region").map(|region| region.to_string()),
+            service: options.get("service").map(|service| service.to_string()),
+        }))
+    }
+
+    // Credentials of the request, the profile set with @auth, the environment
+    // variables, then AWS_PROFILE or the default profile, as in the AWS SDKs
+    pub fn credentials(&self) -> anyhow::Result<AwsCredentials> {
+        if let Some(credentials) = &self.credentials {
+            return Ok(credentials.clone());
+        }
+        if let Some(profile) = &self.profile {
+            return shared_credentials(profile);
+        }
+        if let (Ok(access_key), Ok(secret_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(AwsCredentials {
+                access_key,
+                secret_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+        let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+        shared_credentials(&profile)
+    }
+
+    pub fn region(&self) -> String {
+        self.region
+            .clone()
+            .or_else(|| env::var("AWS_REGION").ok())
+            .or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+            .unwrap_or_else(|| "us-east-1".to_string())
+    }
+
+    pub fn service(&self, host: &str) -> anyhow::Result<String> {
+        match &self.service {
+            Some(service) => Ok(service.clone()),
+            None => host_service(host)
+                .context("AWS service is missing, e.g. @auth aws-sigv4 service=s3"),
+        }
+    }
+
+    // Adds the x-amz-* and authorization headers for the method, URL, headers and body
+    // as sent, each attempt is signed again so the date stays current
+    pub fn sign(&self, request: &mut Request, now: DateTime<Utc>) -> anyhow::Result<()> {
+        let credentials = self.credentials()?;
+        let region = self.region();
+        let service = self.service(request.url().host_str().unwrap_or_default())?;
+
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload = request.body().and_then(Body::as_bytes).unwrap_or_default();
+        let payload_hash = hex_sha256(payload);
+
+        let headers = request.headers_mut();
+        headers.remove(AUTHORIZATION);
+        headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+        headers.insert(
+            "x-amz-content-sha256",
+            HeaderValue::from_str(&payload_hash)?,
+        );
+        if let Some(token) = &credentials.session_token {
+            headers.insert("x-amz-security-token", HeaderValue::from_str(token)?);
+        }
+
+        let authorization =
+            credentials.authorization(request, &region, &service, &payload_hash, now);
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+        Ok(())
+    }
+}
+
+impl AwsCredentials {
+    // The authorization header signing the method, URL, headers and payload hash
+    fn authorization(
+        &self,
+        request: &Request,
+        region: &str,
+        service: &str,
+        payload_hash: &str,
+        now: DateTime<Utc>,
+    ) -> String {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        // sorted lowercase names with trimmed values, repeated headers joined with commas
+        let mut canonical: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let url = request.url();
+        let host = url.host_str().unwrap_or_default();
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        canonical.insert("host".to_string(), vec![host]);
+        for (name, value) in request.headers().iter() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            canonical
+                .entry(name.as_str().to_string())
+                .or_default()
+                .push(value.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        let canonical_headers: String = canonical
+            .iter()
+            .map(|(name, values)| format!("{}:{}\n", name, values.join(",")))
+            .collect();
+        let signed_headers = canonical.keys().cloned().collect::<Vec<_>>().join(";");
+
+        let canonical_request = [
+            request.method().as_str(),
+            &canonical_uri(url.path(), service),
+            &canonical_query(url),
+            &canonical_headers,
+            &signed_headers,
+            payload_hash,
+        ]
+        .join("\n");
+
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex_sha256(canonical_request.as_bytes())
+        );
+
+        let key = [date.as_str(), region, service, "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, data| hmac_sha256(&key, data.as_bytes()),
+            );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        )
+    }
+}
+
+// The service before the region, e.g. execute-api for abc.execute-api.eu-west-1.amazonaws.com,
+// or the last label without one, e.g. s3 for bucket.s3.amazonaws.com
+fn host_service(host: &str) -> Option<String> {
+    let labels: Vec<&str> = host.strip_suffix(".amazonaws.com")?.split('.').collect();
+    let region = labels
+        .iter()
+        .position(|label| label.contains('-') && label.ends_with(|c: char| c.is_ascii_digit()));
+    match region {
+        Some(0) => None,
+        Some(region) => Some(labels[region - 1].to_string()),
+        None => labels.last().map(|label| label.to_string()),
+    }
+}
+
+// Path segments are encoded once for S3 and twice for other services
+fn canonical_uri(path: &str, service: &str) -> String {
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| {
+            let decoded = percent_decode_str(segment).decode_utf8_lossy();
+            let encoded = utf8_percent_encode(&decoded, URI_ENCODE).to_string();
+            match service {
+                "s3" => encoded,
+                _ => utf8_percent_encode(&encoded, URI_ENCODE).to_string(),
+            }
+        })
+        .collect();
+    match segments.join("/") {
+        path if path.is_empty() => "/".to_string(),
+        path => path,
+    }
+}
+
+// Parameters encoded and sorted by name, then value
+fn canonical_query(url: &reqwest::Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            (
+                utf8_percent_encode(&name, URI_ENCODE).to_string(),
+                utf8_percent_encode(&value, URI_ENCODE).to_string(),
+            )
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Access key, secret key and session token of a profile in ~/.aws/credentials
+fn shared_credentials(profile: &str) -> anyhow::Result<AwsCredentials> {
+    let path = env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(|home| PathBuf::from(home).join(".aws").join("credentials"))
+        })
+        .context("AWS credentials not found, set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY")?;
+    let content = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "AWS credentials not found, set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY or create \"{}\"",
+            path.display()
+        )
+    })?;
+
+    let mut section = String::new();
+    let mut values: BTreeMap<String, String> = BTreeMap::new();
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name.trim().to_string();
+        } else if let Some((key, value)) = line.split_once('=') {
+            if section == profile {
+                values.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+    }
+
+    let value = |key: &str| {
+        values.get(key).cloned().with_context(|| {
+            format!(
+                "No {} for profile \"{}\" in \"{}\"",
+                key,
+                profile,
+                path.display()
+            )
+        })
+    };
+    Ok(AwsCredentials {
+        access_key: value("aws_access_key_id")?,
+        secret_key: value("aws_secret_access_key")?,
+        session_token: values.get("aws_session_token").cloned(),
+    })
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use reqwest::{Method, Url};
+
+    // Requests and signatures of the AWS Signature Version 4 test suite
+    fn suite_authorization(
+        method: Method,
+        url: &str,
+        headers: &[(&'static str, &str)],
+        body: &str,
+    ) -> String {
+        let credentials = AwsCredentials {
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let mut request = Request::new(method, Url::parse(url).unwrap());
+        let header_map = request.headers_mut();
+        header_map.insert("x-amz-date", HeaderValue::from_static("20150830T123600Z"));
+        for (name, value) in headers {
+            header_map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let payload_hash = hex_sha256(body.as_bytes());
+        credentials.authorization(&request, "us-east-1", "service", &payload_hash, now)
+    }
+
+    #[test]
+    fn get_vanilla() {
+        assert_eq!(
+            suite_authorization(Method::GET, "https://example.amazonaws.com/", &[], ""),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn get_vanilla_query_order_key() {
+        assert_eq!(
+            suite_authorization(
+                Method::GET,
+                "https://example.amazonaws.com/?Param2=value2&Param1=value1",
+                &[],
+                ""
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn post_x_www_form_urlencoded() {
+        assert_eq!(
+            suite_authorization(
+                Method::POST,
+                "https://example.amazonaws.com/",
+                &[("content-type", "application/x-www-form-urlencoded")],
+                "Param1=value1"
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+        );
+    }
+
+    #[test]
+    fn services_from_host_names() {
+        assert_eq!(
+            host_service("abc123.execute-api.eu-west-1.amazonaws.com").as_deref(),
+            Some("execute-api")
+        );
+        assert_eq!(
+            host_service("bucket.s3.amazonaws.com").as_deref(),
+            Some("s3")
+        );
+        assert_eq!(host_service("example.com"), None);
+    }
+}
@@ -0,0 +1,476 @@
+use anyhow::Context as ErrorContext;
+use chrono::{DateTime, Utc};
+use reqwest::{
+    blocking::{Client, Response},
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{HeaderMap, HeaderValue, CONTENT_LENGTH, HOST, RETRY_AFTER},
+    redirect::Policy,
+    StatusCode, Url,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::{Layer, Service};
+
+use crate::{cookies::CookieJar, parser::HttpRequest};
+
+// Redirects followed unless set with @max-redirects or @no-redirect
+const MAX_REDIRECTS: usize = 10;
+
+// Longest Retry-After honoured, so a server cannot stall a run
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// Longest delay a linear or exponential backoff grows to, unless the base delay is longer
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// What happened on the connection while sending a request
+#[derive(Default)]
+pub struct Trace {
+    pub redirects: Vec<Redirect>,
+    // when the request was handed to the client
+    pub sent: Option<Instant>,
+    // when the last hop was sent, after following a redirect or answering a digest challenge
+    pub hop: Option<Instant>,
+    // of the last hop, not set when no new connection was made
+    pub dns: Option<Duration>,
+    // TCP connect and TLS handshake, including the DNS lookup
+    pub connect: Option<Duration>,
+    // of the last request as built, before the client adds its defaults
+    pub headers: HeaderMap,
+}
+
+impl Trace {
+    // Timings are of the last hop, the ones before are left out
+    pub fn next_hop(&mut self) {
+        self.hop = Some(Instant::now());
+        self.dns = None;
+        self.connect = None;
+    }
+}
+
+pub type TraceLog = Arc<Mutex<Trace>>;
+
+// A redirect that was followed
+pub struct Redirect {
+    pub from: Url,
+    pub status: StatusCode,
+    pub to: Url,
+    // from sending the hop until the redirect was received
+    pub time: Duration,
+}
+
+// Settings a client is built with, requests with the same ones share its connections
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ClientConfig {
+    max_redirects: usize,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    insecure: bool,
+    cookies: bool,
+}
+
+impl ClientConfig {
+    fn from_request(request: &HttpRequest, cookies: bool) -> anyhow::Result<Self> {
+        let max_redirects = match (
+            request.metadata("no-redirect"),
+            request.metadata("max-redirects"),
+        ) {
+            (Some(_), _) => 0,
+            (None, Some(max)) => max
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid @max-redirects \"{}\"", max))?,
+            (None, None) => MAX_REDIRECTS,
+        };
+        let timeout = |key: &str| -> anyhow::Result<Option<Duration>> {
+            request
+                .metadata(key)
+                .map(|timeout| {
+                    parse_duration(timeout)
+                        .with_context(|| format!("Invalid @{} \"{}\"", key, timeout))
+                })
+                .transpose()
+        };
+        Ok(ClientConfig {
+            max_redirects,
+            timeout: timeout("timeout")?,
+            connect_timeout: timeout("connect-timeout")?,
+            insecure: request.metadata("insecure").is_some(),
+            cookies,
+        })
+    }
+}
+
+// Clients built so far, requests are sent one at a time so they share the trace
+pub struct Clients {
+    trace: TraceLog,
+    cookies: Option<Arc<CookieJar>>,
+    clients: HashMap<ClientConfig, Client>,
+}
+
+impl Clients {
+    pub fn new(trace: TraceLog, cookies: Option<Arc<CookieJar>>) -> Self {
+        Clients {
+            trace,
+            cookies,
+            clients: HashMap::new(),
+        }
+    }
+
+    // The client for the settings of a request, built on first use
+    pub fn get(&mut self, request: &HttpRequest, cookies: bool) -> anyhow::Result<Client> {
+        let config = ClientConfig::from_request(request, cookies && self.cookies.is_some())?;
+        if let Some(client) = self.clients.get(&config) {
+            return Ok(client.clone());
+        }
+        let cookies = self.cookies.as_ref().filter(|_| config.cookies);
+        let client = build_client(&config, &self.trace, cookies)?;
+        self.clients.insert(config, client.clone());
+        Ok(client)
+    }
+}
+
+fn build_client(
+    config: &ClientConfig,
+    trace: &TraceLog,
+    cookies: Option<&Arc<CookieJar>>,
+) -> anyhow::Result<Client> {
+    let max_redirects = config.max_redirects;
+    let log = trace.clone();
+    let policy = Policy::custom(move |attempt| {
+        // the redirect response itself is returned when not following
+        if max_redirects == 0 {
+            return attempt.stop();
+        }
+        // previous URLs include the original request
+        if attempt.previous().len() > max_redirects {
+            let error = format!("more than the {} redirect(s) allowed", max_redirects);
+            return attempt.error(error);
+        }
+        if let (Ok(mut trace), Some(from)) = (log.lock(), attempt.previous().last()) {
+            let sent = trace.hop.or(trace.sent);
+            trace.redirects.push(Redirect {
+                from: from.clone(),
+                status: attempt.status(),
+                to: attempt.url().clone(),
+                time: sent.map(|sent| sent.elapsed()).unwrap_or_default(),
+            });
+            trace.next_hop();
+        }
+        attempt.follow()
+    });
+
+    let mut builder = Client::builder();
+    if let Some(cookies) = cookies {
+        builder = builder.cookie_provider(cookies.clone());
+    }
+    if let Some(timeout) = config.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(timeout) = config.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+
+    let client = builder
+        .danger_accept_invalid_certs(config.insecure)
+        .redirect(policy)
+        .dns_resolver(Arc::new(TimedResolver {
+            trace: trace.clone(),
+        }))
+        .connector_layer(TimedConnectLayer {
+            trace: trace.clone(),
+        })
+        .build()?;
+    Ok(client)
+}
+
+// Durations such as 500ms, 5s or 2m, plain numbers are seconds
+pub fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Expected a duration such as 500ms, 5s or 2m"))?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        unit => anyhow::bail!("Unknown duration unit \"{}\", expected ms, s, m or h", unit),
+    };
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| anyhow::anyhow!("Duration \"{}\" is out of range", value))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backoff {
+    Fixed,
+    Linear,
+    Exponential,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RetryOn {
+    Status(u16),
+    // a class of status codes such as 5xx
+    Class(u16),
+    Connect,
+    Timeout,
+}
+
+// When and how often a request is sent again, e.g. "3 backoff=exponential on=502,503,connect"
+#[derive(Debug)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    backoff: Backoff,
+    delay: Duration,
+    on: Vec<RetryOn>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            backoff: Backoff::Exponential,
+            delay: Duration::from_secs(1),
+            on: vec![
+                RetryOn::Status(429),
+                RetryOn::Status(502),
+                RetryOn::Status(503),
+                RetryOn::Status(504),
+                RetryOn::Connect,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_request(request: &HttpRequest) -> anyhow::Result<Self> {
+        match request.metadata("retry") {
+            Some(spec) => Self::parse(spec).with_context(|| format!("Invalid @retry \"{}\"", spec)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut policy = Self::default();
+        let mut parts = spec.split_whitespace();
+        policy.retries = parts
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Expected the number of retries first"))?;
+
+        for option in parts {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected key=value, got \"{}\"", option))?;
+            match key {
+                "backoff" => {
+                    policy.backoff = match value {
+                        "fixed" => Backoff::Fixed,
+                        "linear" => Backoff::Linear,
+                        "exponential" => Backoff::Exponential,
+                        _ => anyhow::bail!(
+                            "Unknown backoff \"{}\", expected fixed, linear or exponential",
+                            value
+                        ),
+                    }
+                }
+                "delay" => policy.delay = parse_duration(value)?,
+                "on" => {
+                    policy.on = value
+                        .split(',')
+                        .map(parse_retry_on)
+                        .collect::<anyhow::Result<_>>()?
+                }
+                _ => anyhow::bail!("Unknown option \"{}\", expected backoff, delay or on", key),
+            }
+        }
+        Ok(policy)
+    }
+
+    // Delay before the next attempt, or None when the result is final
+    pub fn retry_delay(
+        &self,
+        attempt: u32,
+        result: &reqwest::Result<Response>,
+    ) -> Option<Duration> {
+        if attempt >= self.retries {
+            return None;
+        }
+        let retry = match result {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                self.on.iter().any(|on| match on {
+                    RetryOn::Status(code) => *code == status,
+                    RetryOn::Class(class) => status / 100 == *class,
+                    _ => false,
+                })
+            }
+            Err(e) => self.on.iter().any(|on| match on {
+                RetryOn::Connect => e.is_connect(),
+                RetryOn::Timeout => e.is_timeout(),
+                _ => false,
+            }),
+        };
+        if !retry {
+            return None;
+        }
+
+        // the server knows best when to come back
+        let retry_after = result
+            .as_ref()
+            .ok()
+            .and_then(|response| response.headers().get(RETRY_AFTER))
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        Some(match retry_after {
+            Some(delay) => delay.min(MAX_RETRY_AFTER),
+            None => match self.backoff {
+                Backoff::Fixed => self.delay,
+                Backoff::Linear => self
+                    .delay
+                    .checked_mul(attempt.saturating_add(1))
+                    .unwrap_or(Duration::MAX)
+                    .min(MAX_BACKOFF.max(self.delay)),
+                Backoff::Exponential => self
+                    .delay
+                    .checked_mul(2u32.saturating_pow(attempt))
+                    .unwrap_or(Duration::MAX)
+                    .min(MAX_BACKOFF.max(self.delay)),
+            },
+        })
+    }
+}
+
+fn parse_retry_on(value: &str) -> anyhow::Result<RetryOn> {
+    match value.trim() {
+        "connect" => Ok(RetryOn::Connect),
+        "timeout" => Ok(RetryOn::Timeout),
+        value => {
+            if let Some(class) = value.strip_suffix("xx") {
+                if let Ok(class @ 1..=5) = class.parse() {
+                    return Ok(RetryOn::Class(class));
+                }
+            }
+            match value.parse() {
+                Ok(code @ 100..=599) => Ok(RetryOn::Status(code)),
+                _ => anyhow::bail!(
+                    "Unknown retry condition \"{}\", expected a status code, 5xx, connect or timeout",
+                    value
+                ),
+            }
+        }
+    }
+}
+
+// Either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+// Headers as sent, with the host and content length added as for HTTP/1.1, the
+// client's defaults such as accept and accept-encoding are not included
+pub fn wire_headers(url: &Url, headers: &HeaderMap, body: Option<usize>) -> HeaderMap {
+    let mut headers = headers.clone();
+    if let Some(host) = url.host_str() {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        if let Ok(host) = HeaderValue::from_str(&host) {
+            headers.entry(HOST).or_insert(host);
+        }
+    }
+    if let Some(body) = body {
+        headers.entry(CONTENT_LENGTH).or_insert(body.into());
+    }
+    headers
+}
+
+// Resolves names with the system resolver like the default one, timing the lookup
+struct TimedResolver {
+    trace: TraceLog,
+}
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let trace = self.trace.clone();
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs: Vec<_> = tokio::net::lookup_host(format!("{}:0", name.as_str()))
+                .await?
+                .collect();
+            if let Ok(mut trace) = trace.lock() {
+                trace.dns = Some(trace.dns.unwrap_or_default() + start.elapsed());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+// Times establishing new connections
+#[derive(Clone)]
+struct TimedConnectLayer {
+    trace: TraceLog,
+}
+
+impl<S> Layer<S> for TimedConnectLayer {
+    type Service = TimedConnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnect {
+            inner,
+            trace: self.trace.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TimedConnect<S> {
+    inner: S,
+    trace: TraceLog,
+}
+
+impl<S, R> Service<R> for TimedConnect<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let trace = self.trace.clone();
+        let start = Instant::now();
+        let connecting = self.inner.call(request);
+        Box::pin(async move {
+            let connection = connecting.await;
+            if let Ok(mut trace) = trace.lock() {
+                trace.connect = Some(trace.connect.unwrap_or_default() + start.elapsed());
+            }
+            connection
+        })
+    }
+}
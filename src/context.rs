@@ -1,3 +1,4 @@
+use anyhow::Context as ErrorContext;
 use fake::{
     faker::{
         address::raw::{
@@ -37,11 +38,31 @@ use fake::{
 };
 use handlebars::{
     handlebars_helper, Context as VariableContext, Handlebars, Helper, HelperResult, Output,
-    RenderContext,
+    RenderContext, RenderErrorReason,
 };
-use std::{cmp, collections::BTreeMap, env, path::Path, str::FromStr};
+use std::{
+    cmp,
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    crypto::{hash_helper, hmac_helper, jwt_helper},
+    dates,
+    oauth2::{oauth2_helper, OFFLINE},
+    random::{random_from_schema_helper, random_regex},
+    transform::{self, encode_url, escape_json, Encoder, Transform},
+    Args, KeyValue,
+};
+
+pub const ENV_FILE: &str = "http-client.env.json";
+pub const SHARED_ENV: &str = "$shared";
 
-use crate::{Args, KeyValue};
+// Values of {{ }} expressions are marked while rendering so they can be escaped
+// depending on where they end up, {{{ }}} leaves them out
+const ESCAPE_START: char = '\u{E000}';
+const ESCAPE_END: char = '\u{E001}';
 
 pub struct RequestContext<'ctx> {
     data: BTreeMap<String, String>,
@@ -56,13 +77,21 @@ impl<'ctx> RequestContext<'ctx> {
     }
 
     pub fn from_args(args: Args) -> anyhow::Result<Self> {
-        let mut data: BTreeMap<String, String> = args
-            .param
+        let file = args
+            .file
+            .ok_or(anyhow::Error::msg("missing request file"))?;
+        let mut context = Self::new(file, args.param)?;
+        context.load_environment(args.env_file.as_deref(), args.env.as_deref())?;
+        Ok(context)
+    }
+
+    pub fn new(file: String, params: Vec<KeyValue>) -> anyhow::Result<Self> {
+        let mut data: BTreeMap<String, String> = params
             .into_iter()
             .map(|KeyValue(key, value)| (key, value))
             .collect();
 
-        let work_dir = Path::new(&args.file)
+        let work_dir = Path::new(&file)
             .parent()
             .ok_or(anyhow::Error::msg("invalid file path"))?;
 
@@ -70,7 +99,7 @@ impl<'ctx> RequestContext<'ctx> {
             "request::dir".to_string(),
             work_dir.to_str().unwrap().to_string(),
         );
-        data.insert("request::file".to_string(), args.file);
+        data.insert("request::file".to_string(), file);
 
         let registry = Self::new_registry();
 
@@ -79,20 +108,46 @@ impl<'ctx> RequestContext<'ctx> {
 
     fn new_registry() -> Handlebars<'ctx> {
         let mut registry = Handlebars::new();
-        registry.register_escape_fn(handlebars::no_escape);
+        registry.register_escape_fn(mark);
         registry.set_strict_mode(true);
 
         registry.register_helper("$include", Box::new(include_helper));
 
         registry.register_helper("$env", Box::new(env_helper));
+        registry.register_helper("$oauth2", Box::new(oauth2_helper));
+
+        registry.register_helper("$hash", Box::new(hash_helper));
+        registry.register_helper("$hmac", Box::new(hmac_helper));
+        registry.register_helper("$jwt", Box::new(jwt_helper));
+
+        registry.register_helper("$base64", Box::new(Transform(transform::base64)));
+        registry.register_helper(
+            "$base64Decode",
+            Box::new(Transform(transform::base64_decode)),
+        );
+        registry.register_helper("$urlEncode", Box::new(Encoder(transform::url_encode)));
+        registry.register_helper("$jsonEscape", Box::new(Encoder(transform::json_escape)));
+        registry.register_helper("$upper", Box::new(Transform(transform::upper)));
+        registry.register_helper("$lower", Box::new(Transform(transform::lower)));
+        registry.register_helper("$trim", Box::new(Transform(transform::trim)));
+        registry.register_helper("$substring", Box::new(Transform(transform::substring)));
+        registry.register_helper("$replace", Box::new(Transform(transform::replace)));
+        registry.register_helper("$concat", Box::new(Transform(transform::concat)));
+        registry.register_helper("$default", Box::new(Transform(transform::default)));
+
         registry.register_helper("$systemArch", Box::new(arch_helper));
         registry.register_helper("$systemOS", Box::new(os_helper));
         registry.register_helper("$systemFamily", Box::new(family_helper));
-        registry.register_helper("$timestamp", Box::new(timestamp_helper));
+        registry.register_helper("$timestamp", Box::new(Transform(dates::timestamp)));
+        registry.register_helper("$datetime", Box::new(Transform(dates::datetime)));
+        registry.register_helper("$dateAdd", Box::new(Transform(dates::date_add)));
+        registry.register_helper("$randomDate", Box::new(Transform(dates::random_date)));
 
         registry.register_helper("$randomInt", Box::new(random_int));
         registry.register_helper("$randomNumber", Box::new(random_number));
         registry.register_helper("$randomBoolean", Box::new(random_boolean));
+        registry.register_helper("$randomRegex", Box::new(Transform(random_regex)));
+        registry.register_helper("$randomFromSchema", Box::new(random_from_schema_helper));
 
         registry.register_helper("$randomGUID", Box::new(random_guid));
         registry.register_helper("$randomUUIDv1", Box::new(random_uuidv1));
@@ -192,8 +247,66 @@ impl<'ctx> RequestContext<'ctx> {
     }
 
     pub fn render(&self, template: &str) -> anyhow::Result<String> {
+        Ok(unmark(&self.render_marked(template)?))
+    }
+
+    // Values in the path, query and fragment are percent-encoded, the ones
+    // before, e.g. {{baseUrl}} or the host, are left as they are
+    pub fn render_url(&self, template: &str) -> anyhow::Result<String> {
+        let rendered = self.render_marked(template)?;
+        let mut url = String::new();
+        let mut in_path = false;
+        for (marked, text) in segments(&rendered) {
+            match (marked, in_path) {
+                (true, true) => url.push_str(&encode_url(text)),
+                (true, false) => url.push_str(text),
+                (false, _) => {
+                    let scheme = text
+                        .find("://")
+                        .filter(|&end| !text[..end].contains(['/', '?', '#']))
+                        .map_or(0, |end| end + 3);
+                    in_path = in_path || text[scheme..].contains(['/', '?', '#']);
+                    url.push_str(text);
+                }
+            }
+        }
+        Ok(url)
+    }
+
+    // Values inside the strings of a JSON body are escaped, the ones outside,
+    // e.g. numbers or objects, are left as they are
+    pub fn render_body(&self, template: &str, json: bool) -> anyhow::Result<String> {
+        if !json {
+            return self.render(template);
+        }
+        let rendered = self.render_marked(template)?;
+        let mut body = String::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        for (marked, text) in segments(&rendered) {
+            if marked {
+                match in_string {
+                    true => body.push_str(&escape_json(text)),
+                    false => body.push_str(text),
+                }
+                continue;
+            }
+            for char in text.chars() {
+                match char {
+                    _ if escaped => escaped = false,
+                    '\\' if in_string => escaped = true,
+                    '"' => in_string = !in_string,
+                    _ => {}
+                }
+            }
+            body.push_str(text);
+        }
+        Ok(body)
+    }
+
+    fn render_marked(&self, template: &str) -> anyhow::Result<String> {
         self.registry
-            .render_template(template, &self.data)
+            .render_template(&escape_backslashes(template), &self.data)
             .map_err(|e| e.into())
     }
 
@@ -205,9 +318,143 @@ impl<'ctx> RequestContext<'ctx> {
         self.data.insert(key.to_string(), value.to_string());
     }
 
+    pub fn remove_variable(&mut self, key: &str) -> Option<String> {
+        self.data.remove(key)
+    }
+
+    // Only render, helpers like $oauth2 do not make requests
+    pub fn offline(&mut self) {
+        self.variable(OFFLINE, "true");
+    }
+
     pub fn file(&self) -> &str {
         self.data.get("request::file").unwrap()
     }
+
+    pub fn dir(&self) -> &str {
+        self.data.get("request::dir").unwrap()
+    }
+
+    // Load variables from an environment file, the selected environment
+    // takes precedence over shared variables, parameters over both
+    pub fn load_environment(
+        &mut self,
+        env_file: Option<&str>,
+        env: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let path = match env_file {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Path::new(self.dir())
+                .ancestors()
+                .map(|dir| dir.join(ENV_FILE))
+                .find(|path| path.is_file()),
+        };
+        let Some(path) = path else {
+            if let Some(env) = env {
+                anyhow::bail!("Environment \"{}\" not found, no {} file", env, ENV_FILE);
+            }
+            return Ok(());
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to open file \"{}\"", path.display()))?;
+        let environments: serde_json::Value =
+            serde_json::from_str(&content).with_context(|| {
+                format!(
+                    "Environment file should be valid JSON \"{}\"",
+                    path.display()
+                )
+            })?;
+
+        let mut names = vec![SHARED_ENV];
+        if let Some(env) = env {
+            if environments.get(env).is_none() {
+                anyhow::bail!(
+                    "Environment \"{}\" not found in \"{}\"",
+                    env,
+                    path.display()
+                );
+            }
+            names.insert(0, env);
+        }
+
+        for name in names {
+            let variables = environments[name].as_object().into_iter().flatten();
+            for (key, value) in variables {
+                if !self.contains(key) {
+                    let value = match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    self.variable(key, &value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+        match path.is_absolute() {
+            true => path.to_path_buf(),
+            false => Path::new(self.dir()).join(path),
+        }
+    }
+}
+
+fn mark(value: &str) -> String {
+    format!("{}{}{}", ESCAPE_START, value, ESCAPE_END)
+}
+
+pub fn unmark(rendered: &str) -> String {
+    rendered.replace([ESCAPE_START, ESCAPE_END], "")
+}
+
+// Backslashes in quoted strings of {{ }} expressions are kept as written, e.g. for
+// {{$randomRegex '\d{4}'}}, only \\ and escaped quotes are read as escapes
+pub fn escape_backslashes(template: &str) -> String {
+    let mut escaped = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    let mut in_expression = false;
+    let mut quote: Option<char> = None;
+    while let Some(c) = chars.next() {
+        escaped.push(c);
+        match (c, quote) {
+            // \{{ leaves the expression as text
+            ('\\', None) if !in_expression && chars.peek() == Some(&'{') => {
+                escaped.extend(chars.next());
+            }
+            ('{', None) if chars.peek() == Some(&'{') => {
+                in_expression = true;
+                escaped.extend(chars.next());
+            }
+            ('}', None) if chars.peek() == Some(&'}') => {
+                in_expression = false;
+                escaped.extend(chars.next());
+            }
+            ('\'' | '"', None) if in_expression => quote = Some(c),
+            ('\\', Some(quote)) => match chars.peek() {
+                Some(&next) if next == '\\' || next == quote => escaped.extend(chars.next()),
+                _ => escaped.push('\\'),
+            },
+            (c, Some(open)) if c == open => quote = None,
+            _ => {}
+        }
+    }
+    escaped
+}
+
+// Split rendered text into literal text and marked values
+fn segments(rendered: &str) -> impl Iterator<Item = (bool, &str)> {
+    rendered
+        .split(ESCAPE_START)
+        .enumerate()
+        .flat_map(|(index, part)| match (index, part.split_once(ESCAPE_END)) {
+            (0, _) | (_, None) => vec![(false, part)],
+            (_, Some((value, text))) => vec![(true, value), (false, text)],
+        })
+        .filter(|(marked, text)| *marked || !text.is_empty())
 }
 
 // Helper to include files
@@ -218,17 +465,32 @@ fn include_helper(
     _: &mut RenderContext,
     out: &mut dyn Output,
 ) -> HelperResult {
-    let param = h.param(0).unwrap();
-    let path = Path::new(param.relative_path().unwrap());
+    let param = h
+        .param(0)
+        .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("$include", 0))?;
+    // the path can be given as is or as a quoted string
+    let path = match (param.relative_path(), param.value().as_str()) {
+        (Some(path), _) => Path::new(path.as_str()),
+        (None, Some(path)) => Path::new(path),
+        (None, None) => {
+            return Err(RenderErrorReason::InvalidParamType("path should be a string").into())
+        }
+    };
     let path = match path.is_absolute() {
         true => path.to_path_buf(),
         false => {
-            let work_dir = ctx.data()["request::dir"].as_str().unwrap();
+            let work_dir = ctx.data()["request::dir"].as_str().unwrap_or_default();
             Path::new(work_dir).join(path)
         }
     };
-    let content = std::fs::read_to_string(path).unwrap();
-    let rendered = hb.render_template_with_context(&content, ctx)?;
+    let content = std::fs::read_to_string(&path).map_err(|e| {
+        RenderErrorReason::Other(format!(
+            "Failed to include file \"{}\": {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let rendered = hb.render_template_with_context(&escape_backslashes(&content), ctx)?;
     out.write(&rendered)?;
     Ok(())
 }
@@ -240,30 +502,9 @@ handlebars_helper!(env_helper: |param: str| env::var(param).unwrap_or_default())
 handlebars_helper!(arch_helper: |*_args| std::env::consts::ARCH.to_string());
 handlebars_helper!(os_helper: |*_args| std::env::consts::OS.to_string());
 handlebars_helper!(family_helper: |*_args| std::env::consts::FAMILY.to_string());
-handlebars_helper!(timestamp_helper: |{format: str = "unix", offset: str = "utc"}| {
-    let time = match *&offset {
-        "utc" => chrono::Utc::now().into(),
-        "local" => chrono::Local::now().into(),
-        _ => {
-            let tz = chrono::offset::FixedOffset::from_str(offset).unwrap();
-            chrono::Utc::now().with_timezone(&tz)
-        },
-    };
-    match *&format {
-        "unix" => time.timestamp().to_string(),
-        "unix_millis" => time.timestamp_millis().to_string(),
-        "unix_micros" => time.timestamp_micros().to_string(),
-        "rfc2822" => time.to_rfc2822(),
-        "iso8601" | "rfc3339" => time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
-        "iso8601_millis" | "rfc3339_millis" => time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
-        "iso8601_nanos" | "rfc3339_nanos" => time.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
-        _ => time.format(format).to_string(),
-    }
-});
-
 // Helper functions to render random data
 handlebars_helper!(random_int: |{min: i32 = 0, max: i32 = 1000}| (min..=max).fake::<i32>());
-handlebars_helper!(random_number: |{format: str = "#.#"}| NumberWithFormat(locales::EN, &format).fake::<String>());
+handlebars_helper!(random_number: |{format: str = "#.#"}| NumberWithFormat(locales::EN, format).fake::<String>());
 handlebars_helper!(random_boolean: |{ratio: u8 = 50}| Boolean(locales::EN, ratio).fake::<bool>());
 
 handlebars_helper!(random_guid: |*_args| UUIDv4.fake::<String>());
@@ -279,12 +520,12 @@ handlebars_helper!(random_rgb_color: |*_args| RgbColor(locales::EN).fake::<Strin
 handlebars_helper!(random_rgba_color: |*_args| RgbaColor(locales::EN).fake::<String>());
 
 handlebars_helper!(random_word: |{count: usize = 1, min: usize = 0, max: usize = 0, sep: str = " "}|
-    Words(locales::EN, cmp::max(count, min)..cmp::max(count, max)+1).fake::<Vec<String>>().join(&sep));
+    Words(locales::EN, cmp::max(count, min)..cmp::max(count, max)+1).fake::<Vec<String>>().join(sep));
 handlebars_helper!(random_sentence: |{count: usize = 1, min: usize = 0, max: usize = 0, sep: str = " "}|
-    Sentences(locales::EN, cmp::max(count, min)..cmp::max(count, max)+1).fake::<Vec<String>>().join(&sep));
+    Sentences(locales::EN, cmp::max(count, min)..cmp::max(count, max)+1).fake::<Vec<String>>().join(sep));
 handlebars_helper!(random_paragraph: |{count: usize = 1, min: usize = 0, max: usize = 0, sep: str = "\n"}|
     Paragraphs(locales::EN, cmp::max(count, min)..cmp::max(count, max)+1).fake::<Vec<String>>()
-        .into_iter().map(|p| p.replace("\n", &sep)).collect::<Vec<String>>().join(&sep));
+        .into_iter().map(|p| p.replace("\n", sep)).collect::<Vec<String>>().join(sep));
 
 handlebars_helper!(random_full_name: |*_args| FullName(locales::EN).fake::<String>());
 handlebars_helper!(random_first_name: |*_args| FirstName(locales::EN).fake::<String>());
@@ -354,3 +595,30 @@ handlebars_helper!(random_currency_symbol: |*_args| CurrencySymbol(locales::EN).
 handlebars_helper!(random_credit_card_number: |*_args| CreditCardNumber(locales::EN).fake::<String>());
 handlebars_helper!(random_bic: |*_args| Bic(locales::EN).fake::<String>());
 handlebars_helper!(random_isin: |*_args| Isin(locales::EN).fake::<String>());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backslashes_in_quoted_strings() {
+        assert_eq!(
+            escape_backslashes(r"{{$randomRegex '[A-Z]{3}-\d{4}'}}"),
+            r"{{$randomRegex '[A-Z]{3}-\\d{4}'}}"
+        );
+        // already doubled backslashes and escaped quotes are left alone
+        assert_eq!(
+            escape_backslashes(r#"{{$replace id '^(\\d+)$' "\"$1\""}}"#),
+            r#"{{$replace id '^(\\d+)$' "\"$1\""}}"#
+        );
+        assert_eq!(
+            escape_backslashes(r"{{x sep='\'\n'}}"),
+            r"{{x sep='\'\\n'}}"
+        );
+        // text outside of expressions is not a template string
+        assert_eq!(
+            escape_backslashes(r#"{"path": "C:\dir", "id": "{{id}}"} \{{raw '\d'}}"#),
+            r#"{"path": "C:\dir", "id": "{{id}}"} \{{raw '\d'}}"#
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Context as ErrorContext;
+use cookie_store::{serde::json, CookieStore, RawCookie};
+use reqwest::{header::HeaderValue, Url};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::Mutex,
+};
+
+// Cookies shared by all requests in a run
+#[derive(Default)]
+pub struct CookieJar(Mutex<CookieStore>);
+
+impl CookieJar {
+    // An empty jar when the file does not exist yet, expired cookies are dropped
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file \"{}\"", path.display()))?;
+        let store = json::load(BufReader::new(file))
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to parse cookie jar \"{}\"", path.display()))?;
+        Ok(CookieJar(Mutex::new(store)))
+    }
+
+    // Session cookies are kept too, so a login carries over to the next run
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory \"{}\"", parent.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create file \"{}\"", path.display()))?;
+        let store = self
+            .0
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Cookie jar is poisoned"))?;
+        json::save_incl_expired_and_nonpersistent(&store, &mut BufWriter::new(file))
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to write file \"{}\"", path.display()))
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = headers.filter_map(|value| {
+            let value = value.to_str().ok()?;
+            RawCookie::parse(value).map(RawCookie::into_owned).ok()
+        });
+        if let Ok(mut store) = self.0.lock() {
+            store.store_response_cookies(cookies, url);
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let store = self.0.lock().ok()?;
+        let cookies: Vec<String> = store
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        match cookies.is_empty() {
+            true => None,
+            false => HeaderValue::from_str(&cookies.join("; ")).ok(),
+        }
+    }
+}
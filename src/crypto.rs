@@ -0,0 +1,176 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::Utc;
+use handlebars::{
+    Context as VariableContext, Handlebars, Helper, HelperResult, Output, RenderContext,
+    RenderError, RenderErrorReason,
+};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use serde_json::{json, Map, Value};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::transform::{param, text};
+
+// Tokens minted by $jwt expire after an hour unless set otherwise
+const JWT_EXPIRES: i64 = 3600;
+
+// Helper to hash a value, e.g. {{$hash 'sha256' body encoding='base64'}}
+pub fn hash_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &VariableContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let algorithm = param(h, 0, "algorithm")?;
+    let value = param(h, 1, "value")?;
+    let digest = match algorithm.to_ascii_lowercase().as_str() {
+        "md5" => Md5::digest(&value).to_vec(),
+        "sha1" => Sha1::digest(&value).to_vec(),
+        "sha256" => Sha256::digest(&value).to_vec(),
+        "sha384" => Sha384::digest(&value).to_vec(),
+        "sha512" => Sha512::digest(&value).to_vec(),
+        _ => return Err(unsupported(&algorithm)),
+    };
+    out.write(&encode(h, &digest)?)?;
+    Ok(())
+}
+
+// Helper to sign a value, e.g. {{$hmac 'sha256' secret body encoding='hex'}}
+pub fn hmac_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &VariableContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let algorithm = param(h, 0, "algorithm")?;
+    let secret = param(h, 1, "secret")?;
+    let value = param(h, 2, "value")?;
+    let signature = hmac(&algorithm, secret.as_bytes(), value.as_bytes())
+        .ok_or_else(|| unsupported(&algorithm))?;
+    out.write(&encode(h, &signature)?)?;
+    Ok(())
+}
+
+// Helper to mint a signed token, e.g. {{$jwt alg='HS256' secret=secret claims='{"sub":"42"}'}},
+// iat and exp are added unless given in the claims, expires=0 leaves out exp
+pub fn jwt_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &VariableContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let alg = hash_param(h, "alg").unwrap_or_else(|| "HS256".to_string());
+    let secret = hash_param(h, "secret").ok_or_else(|| {
+        RenderErrorReason::Other("$jwt is missing the secret parameter".to_string())
+    })?;
+    let mut claims: Map<String, Value> = match h.hash_get("claims").map(|claims| claims.value()) {
+        None => Map::new(),
+        Some(Value::Object(claims)) => claims.clone(),
+        Some(Value::String(claims)) => serde_json::from_str(claims).map_err(|e| {
+            RenderErrorReason::Other(format!("$jwt claims should be a JSON object: {}", e))
+        })?,
+        Some(_) => {
+            return Err(
+                RenderErrorReason::Other("$jwt claims should be a JSON object".to_string()).into(),
+            )
+        }
+    };
+    let expires = match hash_param(h, "expires") {
+        Some(expires) => expires.parse().map_err(|_| {
+            RenderErrorReason::Other(format!(
+                "$jwt expires should be seconds, got \"{}\"",
+                expires
+            ))
+        })?,
+        None => JWT_EXPIRES,
+    };
+
+    let now = Utc::now().timestamp();
+    let iat = claims
+        .entry("iat")
+        .or_insert(json!(now))
+        .as_i64()
+        .unwrap_or(now);
+    if expires > 0 {
+        claims.entry("exp").or_insert(json!(iat + expires));
+    }
+
+    let mut header = json!({ "alg": alg, "typ": "JWT" });
+    if let Some(kid) = hash_param(h, "kid") {
+        header["kid"] = json!(kid);
+    }
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(Value::Object(claims).to_string())
+    );
+    let algorithm = match alg.as_str() {
+        "HS256" => "sha256",
+        "HS384" => "sha384",
+        "HS512" => "sha512",
+        _ => {
+            return Err(RenderErrorReason::Other(format!(
+                "Unsupported JWT algorithm \"{}\", expected HS256, HS384 or HS512",
+                alg
+            ))
+            .into())
+        }
+    };
+    let signature = hmac(algorithm, secret.as_bytes(), signing_input.as_bytes())
+        .ok_or_else(|| unsupported(algorithm))?;
+    out.write(&format!(
+        "{}.{}",
+        signing_input,
+        URL_SAFE_NO_PAD.encode(signature)
+    ))?;
+    Ok(())
+}
+
+fn hmac(algorithm: &str, secret: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+    fn sign<M: Mac + hmac::digest::KeyInit>(secret: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut mac = <M as Mac>::new_from_slice(secret).expect("HMAC takes keys of any size");
+        mac.update(value);
+        mac.finalize().into_bytes().to_vec()
+    }
+    Some(match algorithm.to_ascii_lowercase().as_str() {
+        "md5" => sign::<Hmac<Md5>>(secret, value),
+        "sha1" => sign::<Hmac<Sha1>>(secret, value),
+        "sha256" => sign::<Hmac<Sha256>>(secret, value),
+        "sha384" => sign::<Hmac<Sha384>>(secret, value),
+        "sha512" => sign::<Hmac<Sha512>>(secret, value),
+        _ => return None,
+    })
+}
+
+// Hex unless encoding='base64' or encoding='base64url' is given
+fn encode(h: &Helper, bytes: &[u8]) -> Result<String, RenderError> {
+    match hash_param(h, "encoding").as_deref().unwrap_or("hex") {
+        "hex" => Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect()),
+        "base64" => Ok(STANDARD.encode(bytes)),
+        "base64url" => Ok(URL_SAFE_NO_PAD.encode(bytes)),
+        encoding => Err(RenderErrorReason::Other(format!(
+            "Unknown encoding \"{}\", expected hex, base64 or base64url",
+            encoding
+        ))
+        .into()),
+    }
+}
+
+fn hash_param(h: &Helper, name: &str) -> Option<String> {
+    h.hash_get(name).map(|value| text(value.value()))
+}
+
+fn unsupported(algorithm: &str) -> RenderError {
+    RenderErrorReason::Other(format!(
+        "Unsupported algorithm \"{}\", expected md5, sha1, sha256, sha384 or sha512",
+        algorithm
+    ))
+    .into()
+}
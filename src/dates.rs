@@ -0,0 +1,279 @@
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Local, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    Offset, SecondsFormat, TimeZone, Timelike, Utc,
+};
+use fake::Fake;
+use handlebars::{Helper, RenderError, RenderErrorReason};
+use serde_json::Value;
+use std::{fmt::Write, str::FromStr};
+
+use crate::transform::{param, text};
+
+// {{$timestamp format='unix' offset='utc'}}
+pub fn timestamp(h: &Helper) -> Result<Value, RenderError> {
+    let time = now(h)?;
+    let format = hash_param(h, "format").unwrap_or_else(|| "unix".to_string());
+    Ok(format_datetime(&time, &format)?.into())
+}
+
+// {{$datetime 'iso8601' add='-1d' startOf='day'}}, from now or from a date given as base,
+// truncated to the start of the unit first, then moved by add
+pub fn datetime(h: &Helper) -> Result<Value, RenderError> {
+    let format = name_param(h, 0)
+        .or_else(|| hash_param(h, "format"))
+        .unwrap_or_else(|| "iso8601".to_string());
+    let time = match hash_param(h, "base") {
+        Some(base) => parse(h, &base)?.0,
+        None => now(h)?,
+    };
+    let time = shift(h, time, hash_param(h, "add").as_deref())?;
+    Ok(format_datetime(&time, &format)?.into())
+}
+
+// {{$dateAdd base '3h'}}, printed like the base unless a format is given
+pub fn date_add(h: &Helper) -> Result<Value, RenderError> {
+    let (time, base_format) = parse(h, &param(h, 0, "base")?)?;
+    let amount = param(h, 1, "amount")?;
+    let time = shift(h, time, Some(&amount))?;
+    let format = hash_param(h, "format").unwrap_or_else(|| base_format.to_string());
+    Ok(format_datetime(&time, &format)?.into())
+}
+
+// {{$randomDate from='2020-01-01' to='now'}}
+pub fn random_date(h: &Helper) -> Result<Value, RenderError> {
+    let from = parse(
+        h,
+        &hash_param(h, "from").unwrap_or_else(|| "now".to_string()),
+    )?
+    .0;
+    let to = parse(h, &hash_param(h, "to").unwrap_or_else(|| "now".to_string()))?.0;
+    if from > to {
+        return Err(RenderErrorReason::Other(format!(
+            "$randomDate from {} is after to {}",
+            from, to
+        ))
+        .into());
+    }
+    let millis = (from.timestamp_millis()..=to.timestamp_millis()).fake::<i64>();
+    let time = from + Duration::milliseconds(millis - from.timestamp_millis());
+    let format = hash_param(h, "format").unwrap_or_else(|| "iso8601".to_string());
+    Ok(format_datetime(&time, &format)?.into())
+}
+
+pub fn format_datetime(time: &DateTime<FixedOffset>, format: &str) -> Result<String, RenderError> {
+    Ok(match format {
+        "unix" => time.timestamp().to_string(),
+        "unix_millis" => time.timestamp_millis().to_string(),
+        "unix_micros" => time.timestamp_micros().to_string(),
+        "rfc2822" => time.to_rfc2822(),
+        "rfc1123" => time
+            .with_timezone(&Utc)
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string(),
+        "iso8601" | "rfc3339" => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "iso8601_millis" | "rfc3339_millis" => time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "iso8601_nanos" | "rfc3339_nanos" => time.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        _ => {
+            let mut formatted = String::new();
+            write!(formatted, "{}", time.format(format)).map_err(|_| {
+                RenderErrorReason::Other(format!("Invalid date format \"{}\"", format))
+            })?;
+            formatted
+        }
+    })
+}
+
+// Now in the offset given as utc, local or e.g. +02:00
+fn now(h: &Helper) -> Result<DateTime<FixedOffset>, RenderError> {
+    Ok(Utc::now().with_timezone(&offset(h)?.unwrap_or(Utc.fix())))
+}
+
+fn offset(h: &Helper) -> Result<Option<FixedOffset>, RenderError> {
+    let Some(offset) = hash_param(h, "offset") else {
+        return Ok(None);
+    };
+    let offset = match offset.as_str() {
+        "utc" => Utc.fix(),
+        "local" => *Local::now().offset(),
+        _ => FixedOffset::from_str(&offset).map_err(|_| {
+            RenderErrorReason::Other(format!(
+                "Invalid offset \"{}\", expected utc, local or e.g. +02:00",
+                offset
+            ))
+        })?,
+    };
+    Ok(Some(offset))
+}
+
+// Parse a date with the format given as parse, or as RFC 3339, RFC 2822, unix
+// seconds or milliseconds, or a date and time without offset, returns the
+// format to print it the same way
+fn parse(h: &Helper, value: &str) -> Result<(DateTime<FixedOffset>, &'static str), RenderError> {
+    let offset = offset(h)?;
+    let value = value.trim();
+    let local = offset.unwrap_or(Utc.fix());
+    let invalid =
+        || RenderErrorReason::Other(format!("{} got an invalid date \"{}\"", h.name(), value));
+
+    let (time, format) = if value == "now" {
+        (now(h)?, "iso8601")
+    } else if let Some(format) = hash_param(h, "parse") {
+        let time = DateTime::parse_from_str(value, &format)
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(value, &format)
+                    .ok()
+                    .or_else(|| {
+                        NaiveDate::parse_from_str(value, &format)
+                            .ok()
+                            .map(|date| date.and_time(NaiveTime::MIN))
+                    })
+                    .and_then(|time| local.from_local_datetime(&time).single())
+            })
+            .ok_or_else(invalid)?;
+        (time, "iso8601")
+    } else if !value.is_empty()
+        && value
+            .trim_start_matches('-')
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+    {
+        let number: i64 = value.parse().map_err(|_| invalid())?;
+        // 13 digits and more are milliseconds, seconds reach that in the year 33658
+        let (time, format) = match value.trim_start_matches('-').len() >= 13 {
+            true => (DateTime::from_timestamp_millis(number), "unix_millis"),
+            false => (DateTime::from_timestamp(number, 0), "unix"),
+        };
+        (time.ok_or_else(invalid)?.fixed_offset(), format)
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        let format = match value.contains('.') {
+            true => "iso8601_millis",
+            false => "iso8601",
+        };
+        (time, format)
+    } else if let Ok(time) = DateTime::parse_from_rfc2822(value) {
+        (time, "rfc2822")
+    } else if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let time = local
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .single()
+            .ok_or_else(invalid)?;
+        (time, "%Y-%m-%d")
+    } else {
+        let time = [
+            "%Y-%m-%dT%H:%M:%S%.f",
+            "%Y-%m-%d %H:%M:%S%.f",
+            "%Y-%m-%d %H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .and_then(|time| local.from_local_datetime(&time).single())
+        .ok_or_else(invalid)?;
+        (time, "iso8601")
+    };
+
+    // dates with their own offset are converted to the one given
+    Ok((
+        time.with_timezone(&offset.unwrap_or(*time.offset())),
+        format,
+    ))
+}
+
+// Truncate to startOf, then move by the amount, e.g. '-1d', '3h' or '1w -2d'
+fn shift(
+    h: &Helper,
+    time: DateTime<FixedOffset>,
+    amount: Option<&str>,
+) -> Result<DateTime<FixedOffset>, RenderError> {
+    let mut time = match hash_param(h, "startOf") {
+        Some(unit) => start_of(time, &unit)?,
+        None => time,
+    };
+    for term in amount.unwrap_or_default().split_whitespace() {
+        let invalid = || {
+            RenderErrorReason::Other(format!(
+                "Invalid date amount \"{}\", expected e.g. -1d, 3h or 1w -2d",
+                term
+            ))
+        };
+        let (negative, unsigned) = match term.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, term.strip_prefix('+').unwrap_or(term)),
+        };
+        let split = unsigned
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (number, unit) = unsigned.split_at(split);
+        let number: u32 = number.parse().map_err(|_| invalid())?;
+        let months = match unit {
+            "M" => Some(number),
+            "y" => number.checked_mul(12),
+            _ => None,
+        };
+        time = match (months, negative) {
+            (Some(months), false) => time.checked_add_months(Months::new(months)),
+            (Some(months), true) => time.checked_sub_months(Months::new(months)),
+            (None, _) => {
+                let duration = match unit {
+                    "ms" => Duration::milliseconds(number.into()),
+                    "s" => Duration::seconds(number.into()),
+                    "m" => Duration::minutes(number.into()),
+                    "h" => Duration::hours(number.into()),
+                    "d" => Duration::days(number.into()),
+                    "w" => Duration::weeks(number.into()),
+                    _ => return Err(invalid().into()),
+                };
+                match negative {
+                    true => time.checked_sub_signed(duration),
+                    false => time.checked_add_signed(duration),
+                }
+            }
+        }
+        .ok_or_else(invalid)?;
+    }
+    Ok(time)
+}
+
+// Start of the minute, hour, day, week, month or year, weeks start on Monday
+fn start_of(time: DateTime<FixedOffset>, unit: &str) -> Result<DateTime<FixedOffset>, RenderError> {
+    let local = time.naive_local();
+    let date = local.date();
+    let start = match unit {
+        "minute" => date.and_hms_opt(local.hour(), local.minute(), 0),
+        "hour" => date.and_hms_opt(local.hour(), 0, 0),
+        "day" => Some(date.and_time(NaiveTime::MIN)),
+        "week" => {
+            let monday = date - Duration::days(date.weekday().num_days_from_monday().into());
+            Some(monday.and_time(NaiveTime::MIN))
+        }
+        "month" => date.with_day(1).map(|date| date.and_time(NaiveTime::MIN)),
+        "year" => date
+            .with_ordinal(1)
+            .map(|date| date.and_time(NaiveTime::MIN)),
+        _ => {
+            return Err(RenderErrorReason::Other(format!(
+                "Invalid startOf \"{}\", expected minute, hour, day, week, month or year",
+                unit
+            ))
+            .into())
+        }
+    };
+    start
+        .and_then(|start| time.offset().from_local_datetime(&start).single())
+        .ok_or_else(|| {
+            RenderErrorReason::Other(format!("No start of the {} for {}", unit, time)).into()
+        })
+}
+
+fn name_param(h: &Helper, index: usize) -> Option<String> {
+    // the name can be given as is or as a quoted string
+    let param = h.param(index)?;
+    match param.relative_path() {
+        Some(path) if param.is_value_missing() => Some(path.to_string()),
+        _ => Some(text(param.value())),
+    }
+}
+
+fn hash_param(h: &Helper, name: &str) -> Option<String> {
+    h.hash_get(name).map(|value| text(value.value()))
+}
@@ -0,0 +1,318 @@
+use clap::Subcommand;
+use percent_encoding::percent_decode_str;
+use reqwest::{Method, Url, Version};
+use serde_json::Value;
+use std::borrow::Cow;
+
+use crate::{
+    context::RequestContext,
+    parse_param,
+    parser::{parse_http_file, HttpRequest},
+    KeyValue,
+};
+
+const MASK: &str = "****";
+
+#[derive(Subcommand, Debug)]
+pub enum ExportCommand {
+    /// Print requests as curl command lines
+    Curl {
+        /// Path to a HTTP request file
+        #[arg(short, long, name = "FILE")]
+        file: String,
+
+        /// Parameters to be passed to the request
+        /// in the form of key=value
+        #[arg(short, long, name = "key=value", value_parser = parse_param)]
+        param: Vec<KeyValue>,
+
+        /// Name of the environment to use from the environment file
+        #[arg(short, long, name = "ENV")]
+        env: Option<String>,
+
+        /// Path to an environment file
+        #[arg(long, name = "ENV_FILE")]
+        env_file: Option<String>,
+
+        /// Name of the request to export, all requests when not given
+        #[arg(short, long, name = "NAME")]
+        request: Option<String>,
+
+        /// Show secrets such as credentials and tokens as is
+        #[arg(long)]
+        reveal: bool,
+    },
+}
+
+pub fn export(command: ExportCommand) -> anyhow::Result<()> {
+    match command {
+        ExportCommand::Curl {
+            file,
+            param,
+            env,
+            env_file,
+            request,
+            reveal,
+        } => {
+            let mut context = RequestContext::new(file, param)?;
+            context.load_environment(env_file.as_deref(), env.as_deref())?;
+            context.offline();
+            let requests = select_requests(parse_http_file(&mut context)?, request.as_deref())?;
+            let commands: Vec<String> = requests
+                .iter()
+                .map(|request| curl_command(request, reveal))
+                .collect();
+            println!("{}", commands.join("\n\n"));
+            Ok(())
+        }
+    }
+}
+
+fn select_requests(
+    requests: Vec<HttpRequest>,
+    name: Option<&str>,
+) -> anyhow::Result<Vec<HttpRequest>> {
+    let Some(name) = name else {
+        return Ok(requests);
+    };
+    let selected: Vec<HttpRequest> = requests
+        .into_iter()
+        .filter(|request| request.metadata("name") == Some(name))
+        .collect();
+    if selected.is_empty() {
+        anyhow::bail!("No request named \"{}\"", name);
+    }
+    Ok(selected)
+}
+
+fn curl_command(request: &HttpRequest, reveal: bool) -> String {
+    let mut args: Vec<String> = vec!["curl".to_string()];
+
+    match request.method {
+        Method::GET => {}
+        Method::HEAD => args.push("--head".to_string()),
+        ref method => args.push(format!("-X {}", method)),
+    }
+    match request.version {
+        Version::HTTP_10 => args.push("--http1.0".to_string()),
+        Version::HTTP_2 => args.push("--http2".to_string()),
+        Version::HTTP_3 => args.push("--http3".to_string()),
+        _ => {}
+    }
+    if request.metadata("insecure").is_some() {
+        args.push("-k".to_string());
+    }
+
+    let url = match reveal {
+        true => request.url.to_string(),
+        false => mask_url(&request.url),
+    };
+    args.push(shell_quote(&url).to_string());
+
+    for (name, value) in request.headers.iter() {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        let value = match reveal || !is_secret(name.as_str()) {
+            true => value.to_string(),
+            // keep the authentication scheme visible, e.g. "Bearer ****"
+            false => match value.split_once(' ') {
+                Some((scheme, _)) if name == "authorization" => format!("{} {}", scheme, MASK),
+                _ => MASK.to_string(),
+            },
+        };
+        args.push(format!(
+            "-H {}",
+            shell_quote(&format!("{}: {}", name, value))
+        ));
+    }
+
+    if let Some(digest) = &request.digest {
+        let password = match reveal {
+            true => digest.password.as_str(),
+            false => MASK,
+        };
+        args.push(format!(
+            "--digest -u {}",
+            shell_quote(&format!("{}:{}", digest.username, password))
+        ));
+    }
+
+    // curl signs the request itself, credentials are looked up without signing
+    if let Some(aws) = &request.aws {
+        let mut scope = format!("aws:amz:{}", aws.region());
+        if let Ok(service) = aws.service(request.url.host_str().unwrap_or_default()) {
+            scope = format!("{}:{}", scope, service);
+        }
+        let (access_key, secret_key, session_token) = match aws.credentials() {
+            Ok(credentials) => (
+                credentials.access_key,
+                credentials.secret_key,
+                credentials.session_token,
+            ),
+            Err(_) => ("<access key>".to_string(), "<secret key>".to_string(), None),
+        };
+        let secret_key = match reveal {
+            true => secret_key.as_str(),
+            false => MASK,
+        };
+        args.push(format!("--aws-sigv4 {}", shell_quote(&scope)));
+        args.push(format!(
+            "-u {}",
+            shell_quote(&format!("{}:{}", access_key, secret_key))
+        ));
+        if let Some(token) = session_token {
+            let token = match reveal {
+                true => token.as_str(),
+                false => MASK,
+            };
+            args.push(format!(
+                "-H {}",
+                shell_quote(&format!("x-amz-security-token: {}", token))
+            ));
+        }
+    }
+
+    if let Some(body) = &request.body {
+        let body = String::from_utf8_lossy(body);
+        let body = match reveal {
+            true => body.to_string(),
+            false => mask_body(&body),
+        };
+        args.push(format!("--data-raw {}", shell_quote(&body)));
+    }
+
+    args.join(" \\\n  ")
+}
+
+// Header, parameter and field names holding credentials, e.g. Authorization,
+// api_key, clientSecret or x-amz-security-token, but not author or session_count
+fn is_secret(name: &str) -> bool {
+    const SECRETS: [&str; 20] = [
+        "authorization",
+        "proxy-authorization",
+        "auth",
+        "cookie",
+        "set-cookie",
+        "x-api-key",
+        "api-key",
+        "apikey",
+        "key",
+        "token",
+        "secret",
+        "password",
+        "passwd",
+        "pwd",
+        "signature",
+        "sig",
+        "session",
+        "session-id",
+        "sessionid",
+        "assertion",
+    ];
+    const SUFFIXES: [&str; 5] = ["-token", "-secret", "-password", "-api-key", "-apikey"];
+    let name = normalize_name(name);
+    SECRETS.contains(&name.as_str()) || SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+// Lowercase with words separated by -, e.g. access_token and accessToken become access-token
+fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_uppercase() && previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+            normalized.push('-');
+        }
+        normalized.extend(c.to_lowercase());
+        previous = Some(c);
+    }
+    normalized.replace('_', "-")
+}
+
+// Only the values of secret parameters are replaced, the rest of the URL is kept as written
+fn mask_url(url: &Url) -> String {
+    let mut masked = url.clone();
+    if url.password().is_some() {
+        let _ = masked.set_password(Some(MASK));
+    }
+    if let Some(query) = url.query() {
+        let mut secret = false;
+        let pairs: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if is_secret(&percent_decode_str(key).decode_utf8_lossy()) => {
+                    secret = true;
+                    format!("{}={}", key, MASK)
+                }
+                _ => pair.to_string(),
+            })
+            .collect();
+        if secret {
+            masked.set_query(Some(&pairs.join("&")));
+        }
+    }
+    masked.to_string()
+}
+
+fn mask_body(body: &str) -> String {
+    if let Ok(mut json) = serde_json::from_str::<Value>(body) {
+        if !mask_json(&mut json) {
+            return body.to_string();
+        }
+        // kept on one line unless it was written over several
+        let masked = match body.trim().contains('\n') {
+            true => serde_json::to_string_pretty(&json),
+            false => serde_json::to_string(&json),
+        };
+        return masked.unwrap_or_default();
+    }
+    // form encoded bodies, e.g. username=bob&password=secret
+    let is_form =
+        !body.contains(char::is_whitespace) && body.split('&').all(|pair| pair.contains('='));
+    if is_form {
+        return body
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if is_secret(&percent_decode_str(key).decode_utf8_lossy()) => {
+                    format!("{}={}", key, MASK)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("&");
+    }
+    body.to_string()
+}
+
+fn mask_json(value: &mut Value) -> bool {
+    let mut masked = false;
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret(key) && !value.is_object() && !value.is_array() {
+                    *value = Value::String(MASK.to_string());
+                    masked = true;
+                } else {
+                    masked |= mask_json(value);
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                masked |= mask_json(value);
+            }
+        }
+        _ => {}
+    }
+    masked
+}
+
+// Quote a value for POSIX shells when it contains anything but safe characters
+fn shell_quote(value: &str) -> Cow<'_, str> {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    match safe {
+        true => Cow::Borrowed(value),
+        false => Cow::Owned(format!("'{}'", value.replace('\'', "'\\''"))),
+    }
+}
@@ -0,0 +1,806 @@
+use anyhow::{anyhow, Context};
+use serde_json::{Number, Value};
+use std::{cmp::Ordering, fmt, iter::Peekable, str::Chars};
+
+// A subset of jq filters, e.g. `.data[] | select(.age > 30) | .name`.
+// JSONPath style paths such as `$.data[*].name` are accepted as well.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Identity,
+    Recurse,
+    Literal(Value),
+    Path(Box<Expr>, Step, bool),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Alternative(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+    Array(Option<Box<Expr>>),
+    Function(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    Iterate,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// Supported functions and their number of arguments
+const FUNCTIONS: [(&str, usize); 11] = [
+    ("select", 1),
+    ("map", 1),
+    ("keys", 0),
+    ("length", 0),
+    ("first", 0),
+    ("first", 1),
+    ("last", 0),
+    ("type", 0),
+    ("not", 0),
+    ("empty", 0),
+    ("recurse", 0),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dot,
+    DotDot,
+    Dollar,
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Pipe,
+    Comma,
+    Colon,
+    Semicolon,
+    Question,
+    Star,
+    Minus,
+    Alternative,
+    Op(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Dot => write!(f, "."),
+            Token::DotDot => write!(f, ".."),
+            Token::Dollar => write!(f, "$"),
+            Token::Ident(ident) => write!(f, "{}", ident),
+            Token::Str(value) => write!(f, "{}", value),
+            Token::Num(value) => write!(f, "{}", value),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Pipe => write!(f, "|"),
+            Token::Comma => write!(f, ","),
+            Token::Colon => write!(f, ":"),
+            Token::Semicolon => write!(f, ";"),
+            Token::Question => write!(f, "?"),
+            Token::Star => write!(f, "*"),
+            Token::Minus => write!(f, "-"),
+            Token::Alternative => write!(f, "//"),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+// Describe a token in an error message, the end of the filter when there is none
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        Some(token) => format!("\"{}\"", token),
+        None => "end of filter".to_string(),
+    }
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(source)?;
+        if tokens.is_empty() {
+            anyhow::bail!("Filter is empty, use . for the whole body");
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser
+            .pipe()
+            .with_context(|| format!("Invalid filter \"{}\"", source))?;
+        if let Some(token) = parser.peek() {
+            anyhow::bail!(
+                "Unexpected {} in filter \"{}\"",
+                describe(Some(token)),
+                source
+            );
+        }
+        Ok(Filter { expr })
+    }
+
+    pub fn apply(&self, input: &Value) -> anyhow::Result<Vec<Value>> {
+        eval(&self.expr, input)
+    }
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<Chars> = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                Token::DotDot
+            }
+            '.' => Token::Dot,
+            '$' => Token::Dollar,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '|' => Token::Pipe,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '?' => Token::Question,
+            '*' => Token::Star,
+            '-' => Token::Minus,
+            '/' if chars.peek() == Some(&'/') => {
+                chars.next();
+                Token::Alternative
+            }
+            '=' | '!' | '<' | '>' => {
+                let mut op = c.to_string();
+                if chars.peek() == Some(&'=') {
+                    op.push('=');
+                    chars.next();
+                }
+                if op == "=" || op == "!" {
+                    anyhow::bail!("Unexpected \"{}\" in filter, use == or !=", op);
+                }
+                Token::Op(op)
+            }
+            '"' => {
+                // reuse the JSON string rules for escapes
+                let mut literal = String::from('"');
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            literal.push('\\');
+                            literal.extend(chars.next());
+                        }
+                        Some(c) => literal.push(c),
+                        None => anyhow::bail!("Unterminated string in filter"),
+                    }
+                }
+                literal.push('"');
+                Token::Str(serde_json::from_str(&literal)?)
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_digit() && c != '.' && c != 'e' && c != 'E' {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                Token::Num(
+                    number
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid number \"{}\" in filter", number))?,
+                )
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                Token::Ident(ident)
+            }
+            c => anyhow::bail!("Unexpected \"{}\" in filter", c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: Token) -> anyhow::Result<()> {
+        match self.next() {
+            Some(next) if next == token => Ok(()),
+            next => Err(anyhow!(
+                "Expected \"{}\" in filter, got {}",
+                token,
+                describe(next.as_ref())
+            )),
+        }
+    }
+
+    fn pipe(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.comma()?;
+        while self.eat(&Token::Pipe) {
+            expr = Expr::Pipe(Box::new(expr), Box::new(self.comma()?));
+        }
+        Ok(expr)
+    }
+
+    fn comma(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.alternative()?;
+        while self.eat(&Token::Comma) {
+            expr = Expr::Comma(Box::new(expr), Box::new(self.alternative()?));
+        }
+        Ok(expr)
+    }
+
+    fn alternative(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.or()?;
+        while self.eat(&Token::Alternative) {
+            expr = Expr::Alternative(Box::new(expr), Box::new(self.or()?));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Ident("or".to_string())) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.compare()?;
+        while self.eat(&Token::Ident("and".to_string())) {
+            expr = Expr::And(Box::new(expr), Box::new(self.compare()?));
+        }
+        Ok(expr)
+    }
+
+    fn compare(&mut self) -> anyhow::Result<Expr> {
+        let expr = self.postfix()?;
+        let op = match self.peek() {
+            Some(Token::Op(op)) => match op.as_str() {
+                "==" => CompareOp::Eq,
+                "!=" => CompareOp::Ne,
+                "<" => CompareOp::Lt,
+                "<=" => CompareOp::Le,
+                ">" => CompareOp::Gt,
+                _ => CompareOp::Ge,
+            },
+            _ => return Ok(expr),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(Box::new(expr), op, Box::new(self.postfix()?)))
+    }
+
+    fn postfix(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            let step = match self.peek() {
+                Some(Token::Dot) => match self.tokens.get(self.pos + 1) {
+                    Some(Token::Ident(key)) | Some(Token::Str(key)) => {
+                        let key = key.clone();
+                        self.pos += 2;
+                        Step::Key(key)
+                    }
+                    Some(Token::LBracket) => {
+                        self.pos += 2;
+                        self.bracket()?
+                    }
+                    _ => break,
+                },
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    self.bracket()?
+                }
+                _ => break,
+            };
+            let optional = self.eat(&Token::Question);
+            expr = Expr::Path(Box::new(expr), step, optional);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Expr> {
+        match self.next() {
+            // a leading dot is the input itself, a path step follows in postfix
+            Some(Token::Dot) => {
+                match self.peek() {
+                    Some(Token::Ident(_)) | Some(Token::Str(_)) | Some(Token::LBracket) => {
+                        self.pos -= 1;
+                    }
+                    _ => {}
+                }
+                Ok(Expr::Identity)
+            }
+            Some(Token::Dollar) => Ok(Expr::Identity),
+            Some(Token::DotDot) => Ok(Expr::Recurse),
+            Some(Token::Str(value)) => Ok(Expr::Literal(Value::String(value))),
+            Some(Token::Num(value)) => Ok(Expr::Literal(number(value))),
+            Some(Token::Minus) => match self.next() {
+                Some(Token::Num(value)) => Ok(Expr::Literal(number(-value))),
+                token => Err(anyhow!(
+                    "Expected a number in filter, got {}",
+                    describe(token.as_ref())
+                )),
+            },
+            Some(Token::LParen) => {
+                let expr = self.pipe()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::LBracket) => {
+                if self.eat(&Token::RBracket) {
+                    return Ok(Expr::Array(None));
+                }
+                let expr = self.pipe()?;
+                self.expect(Token::RBracket)?;
+                Ok(Expr::Array(Some(Box::new(expr))))
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ => {
+                    let mut args = Vec::new();
+                    if self.eat(&Token::LParen) {
+                        args.push(self.pipe()?);
+                        while self.eat(&Token::Semicolon) {
+                            args.push(self.pipe()?);
+                        }
+                        self.expect(Token::RParen)?;
+                    }
+                    if !FUNCTIONS.contains(&(name.as_str(), args.len())) {
+                        anyhow::bail!("Unknown filter function {}/{}", name, args.len());
+                    }
+                    Ok(Expr::Function(name, args))
+                }
+            },
+            // e.g. an empty filter or a trailing pipe
+            None => Err(anyhow!(
+                "Expected an expression in filter, got end of filter"
+            )),
+            token => Err(anyhow!("Unexpected {} in filter", describe(token.as_ref()))),
+        }
+    }
+
+    // The part after an opening bracket, e.g. [], [*], [0], [-1], [1:3] or ["key"]
+    fn bracket(&mut self) -> anyhow::Result<Step> {
+        if self.eat(&Token::RBracket) {
+            return Ok(Step::Iterate);
+        }
+        if self.eat(&Token::Star) {
+            self.expect(Token::RBracket)?;
+            return Ok(Step::Iterate);
+        }
+        if let Some(Token::Str(key)) = self.peek().cloned() {
+            self.pos += 1;
+            self.expect(Token::RBracket)?;
+            return Ok(Step::Key(key));
+        }
+        let start = self.index()?;
+        if self.eat(&Token::Colon) {
+            let end = self.index()?;
+            self.expect(Token::RBracket)?;
+            return Ok(Step::Slice(start, end));
+        }
+        self.expect(Token::RBracket)?;
+        start
+            .map(Step::Index)
+            .ok_or_else(|| anyhow!("Expected an index in filter"))
+    }
+
+    fn index(&mut self) -> anyhow::Result<Option<i64>> {
+        let negative = self.eat(&Token::Minus);
+        match self.peek() {
+            Some(Token::Num(value)) => {
+                let value = *value as i64;
+                self.pos += 1;
+                Ok(Some(if negative { -value } else { value }))
+            }
+            _ if negative => Err(anyhow!("Expected a number in filter")),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn number(value: f64) -> Value {
+    match value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        true => Value::Number((value as i64).into()),
+        false => Number::from_f64(value).map_or(Value::Null, Value::Number),
+    }
+}
+
+fn eval(expr: &Expr, input: &Value) -> anyhow::Result<Vec<Value>> {
+    match expr {
+        Expr::Identity => Ok(vec![input.clone()]),
+        Expr::Recurse => {
+            let mut values = Vec::new();
+            recurse(input, &mut values);
+            Ok(values)
+        }
+        Expr::Literal(value) => Ok(vec![value.clone()]),
+        Expr::Path(base, step, optional) => {
+            let mut values = Vec::new();
+            for value in eval(base, input)? {
+                match (apply_step(&value, step), optional) {
+                    (Ok(results), _) => values.extend(results),
+                    (Err(_), true) => {}
+                    (Err(e), false) => return Err(e),
+                }
+            }
+            Ok(values)
+        }
+        Expr::Pipe(left, right) => {
+            let mut values = Vec::new();
+            for value in eval(left, input)? {
+                values.extend(eval(right, &value)?);
+            }
+            Ok(values)
+        }
+        Expr::Comma(left, right) => {
+            let mut values = eval(left, input)?;
+            values.extend(eval(right, input)?);
+            Ok(values)
+        }
+        Expr::Alternative(left, right) => {
+            let values: Vec<Value> = eval(left, input)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(truthy)
+                .collect();
+            match values.is_empty() {
+                true => eval(right, input),
+                false => Ok(values),
+            }
+        }
+        Expr::Or(left, right) => {
+            let mut values = Vec::new();
+            for left in eval(left, input)? {
+                if truthy(&left) {
+                    values.push(Value::Bool(true));
+                    continue;
+                }
+                for right in eval(right, input)? {
+                    values.push(Value::Bool(truthy(&right)));
+                }
+            }
+            Ok(values)
+        }
+        Expr::And(left, right) => {
+            let mut values = Vec::new();
+            for left in eval(left, input)? {
+                if !truthy(&left) {
+                    values.push(Value::Bool(false));
+                    continue;
+                }
+                for right in eval(right, input)? {
+                    values.push(Value::Bool(truthy(&right)));
+                }
+            }
+            Ok(values)
+        }
+        Expr::Compare(left, op, right) => {
+            let mut values = Vec::new();
+            for right in eval(right, input)? {
+                for left in eval(left, input)? {
+                    let ordering = compare(&left, &right);
+                    values.push(Value::Bool(match op {
+                        CompareOp::Eq => ordering == Ordering::Equal,
+                        CompareOp::Ne => ordering != Ordering::Equal,
+                        CompareOp::Lt => ordering == Ordering::Less,
+                        CompareOp::Le => ordering != Ordering::Greater,
+                        CompareOp::Gt => ordering == Ordering::Greater,
+                        CompareOp::Ge => ordering != Ordering::Less,
+                    }));
+                }
+            }
+            Ok(values)
+        }
+        Expr::Array(None) => Ok(vec![Value::Array(Vec::new())]),
+        Expr::Array(Some(expr)) => Ok(vec![Value::Array(eval(expr, input)?)]),
+        Expr::Function(name, args) => call(name, args, input),
+    }
+}
+
+fn apply_step(value: &Value, step: &Step) -> anyhow::Result<Vec<Value>> {
+    match (step, value) {
+        (Step::Key(_), Value::Null) | (Step::Index(_), Value::Null) => Ok(vec![Value::Null]),
+        (Step::Key(key), Value::Object(map)) => {
+            Ok(vec![map.get(key).cloned().unwrap_or(Value::Null)])
+        }
+        (Step::Index(index), Value::Array(values)) => {
+            let index = match *index < 0 {
+                true => values.len() as i64 + index,
+                false => *index,
+            };
+            Ok(vec![usize::try_from(index)
+                .ok()
+                .and_then(|index| values.get(index))
+                .cloned()
+                .unwrap_or(Value::Null)])
+        }
+        (Step::Slice(start, end), Value::Array(values)) => {
+            let (start, end) = slice_bounds(values.len(), *start, *end);
+            Ok(vec![Value::Array(values[start..end].to_vec())])
+        }
+        (Step::Slice(start, end), Value::String(value)) => {
+            let chars: Vec<char> = value.chars().collect();
+            let (start, end) = slice_bounds(chars.len(), *start, *end);
+            Ok(vec![Value::String(chars[start..end].iter().collect())])
+        }
+        (Step::Iterate, Value::Array(values)) => Ok(values.clone()),
+        (Step::Iterate, Value::Object(map)) => Ok(map.values().cloned().collect()),
+        (Step::Key(key), value) => Err(anyhow!(
+            "Cannot index {} with \"{}\"",
+            type_name(value),
+            key
+        )),
+        (Step::Index(index), value) => Err(anyhow!(
+            "Cannot index {} with number {}",
+            type_name(value),
+            index
+        )),
+        (Step::Slice(..), value) => Err(anyhow!("Cannot slice {}", type_name(value))),
+        (Step::Iterate, value) => Err(anyhow!("Cannot iterate over {}", type_name(value))),
+    }
+}
+
+fn slice_bounds(len: usize, start: Option<i64>, end: Option<i64>) -> (usize, usize) {
+    let resolve = |index: i64| match index < 0 {
+        true => (len as i64 + index).max(0) as usize,
+        false => (index as usize).min(len),
+    };
+    let start = start.map_or(0, resolve);
+    let end = end.map_or(len, resolve);
+    (start, end.max(start))
+}
+
+fn call(name: &str, args: &[Expr], input: &Value) -> anyhow::Result<Vec<Value>> {
+    match (name, args) {
+        ("select", [condition]) => {
+            let mut values = Vec::new();
+            for result in eval(condition, input)? {
+                if truthy(&result) {
+                    values.push(input.clone());
+                }
+            }
+            Ok(values)
+        }
+        ("map", [expr]) => {
+            let mut values = Vec::new();
+            for value in apply_step(input, &Step::Iterate)? {
+                values.extend(eval(expr, &value)?);
+            }
+            Ok(vec![Value::Array(values)])
+        }
+        ("keys", []) => match input {
+            Value::Object(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                Ok(vec![Value::Array(
+                    keys.into_iter().cloned().map(Value::String).collect(),
+                )])
+            }
+            Value::Array(values) => Ok(vec![Value::Array(
+                (0..values.len()).map(Value::from).collect(),
+            )]),
+            value => Err(anyhow!("{} has no keys", type_name(value))),
+        },
+        ("length", []) => Ok(vec![match input {
+            Value::Null => Value::from(0),
+            Value::Bool(_) => anyhow::bail!("boolean has no length"),
+            Value::Number(number) => number
+                .as_f64()
+                .and_then(|number| Number::from_f64(number.abs()))
+                .map_or(Value::Null, Value::Number),
+            Value::String(value) => Value::from(value.chars().count()),
+            Value::Array(values) => Value::from(values.len()),
+            Value::Object(map) => Value::from(map.len()),
+        }]),
+        ("first", []) => apply_step(input, &Step::Index(0)),
+        ("last", []) => apply_step(input, &Step::Index(-1)),
+        ("first", [expr]) => Ok(eval(expr, input)?.into_iter().take(1).collect()),
+        ("type", []) => Ok(vec![Value::String(type_name(input).to_string())]),
+        ("not", []) => Ok(vec![Value::Bool(!truthy(input))]),
+        ("empty", []) => Ok(Vec::new()),
+        ("recurse", []) => eval(&Expr::Recurse, input),
+        _ => anyhow::bail!("Unknown filter function {}/{}", name, args.len()),
+    }
+}
+
+fn recurse(value: &Value, values: &mut Vec<Value>) {
+    values.push(value.clone());
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| recurse(item, values)),
+        Value::Object(map) => map.values().for_each(|item| recurse(item, values)),
+        _ => {}
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// jq ordering: null < false < true < numbers < strings < arrays < objects
+fn compare(left: &Value, right: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    };
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left
+            .as_f64()
+            .partial_cmp(&right.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(left), Value::String(right)) => left.cmp(right),
+        (Value::Array(left), Value::Array(right)) => {
+            for (left, right) in left.iter().zip(right) {
+                let ordering = compare(left, right);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            left.len().cmp(&right.len())
+        }
+        (Value::Object(_), Value::Object(_)) => match left == right {
+            true => Ordering::Equal,
+            false => left.to_string().cmp(&right.to_string()),
+        },
+        _ => rank(left).cmp(&rank(right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn apply(filter: &str, input: Value) -> Vec<Value> {
+        Filter::parse(filter).unwrap().apply(&input).unwrap()
+    }
+
+    fn parse_error(filter: &str) -> String {
+        format!("{:#}", Filter::parse(filter).unwrap_err())
+    }
+
+    #[test]
+    fn paths() {
+        let input = json!({"a": {"b": [1, 2, 3]}, "c d": true});
+        assert_eq!(apply(".", input.clone()), vec![input.clone()]);
+        assert_eq!(apply(".a.b[0]", input.clone()), vec![json!(1)]);
+        assert_eq!(apply(".a.b[-1]", input.clone()), vec![json!(3)]);
+        assert_eq!(apply(".a.b[1:]", input.clone()), vec![json!([2, 3])]);
+        assert_eq!(apply(".\"c d\"", input.clone()), vec![json!(true)]);
+        assert_eq!(apply(".missing.b", input.clone()), vec![Value::Null]);
+        assert_eq!(apply("$.a.b[*]", input), vec![json!(1), json!(2), json!(3)]);
+    }
+
+    #[test]
+    fn iterate() {
+        let input = json!({"items": [{"id": 1}, {"id": 2}]});
+        assert_eq!(
+            apply(".items[].id", input.clone()),
+            vec![json!(1), json!(2)]
+        );
+        assert_eq!(apply("[.items[].id]", input.clone()), vec![json!([1, 2])]);
+        assert_eq!(apply(".items | length", input.clone()), vec![json!(2)]);
+        assert!(Filter::parse(".items[].id.x")
+            .unwrap()
+            .apply(&json!({"items": [1]}))
+            .is_err());
+        assert_eq!(
+            apply(".items[]?.id?", json!({"items": 1})),
+            Vec::<Value>::new()
+        );
+    }
+
+    #[test]
+    fn select() {
+        let input = json!([{"name": "a", "age": 20}, {"name": "b", "age": 40}]);
+        assert_eq!(
+            apply(".[] | select(.age > 30) | .name", input.clone()),
+            vec![json!("b")]
+        );
+        assert_eq!(
+            apply(
+                "map(select(.age < 30 or .name == \"b\") | .name)",
+                input.clone()
+            ),
+            vec![json!(["a", "b"])]
+        );
+        assert_eq!(
+            apply(".[0].missing // \"none\"", input),
+            vec![json!("none")]
+        );
+    }
+
+    #[test]
+    fn pipes() {
+        let input = json!({"user": {"tags": ["x", "y"]}});
+        assert_eq!(
+            apply(".user | .tags | first, last", input.clone()),
+            vec![json!("x"), json!("y")]
+        );
+        assert_eq!(apply(".user | keys", input), vec![json!(["tags"])]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_error("").contains("Filter is empty"));
+        assert!(parse_error("   ").contains("Filter is empty"));
+        assert!(parse_error(".a |").contains("end of filter"));
+        assert!(parse_error(".a |").contains("Invalid filter \".a |\""));
+        assert!(parse_error(".a ,").contains("end of filter"));
+        assert!(parse_error("select(").contains("end of filter"));
+        assert!(parse_error(".a ]").contains("Unexpected \"]\""));
+        assert!(parse_error(".a = 1").contains("use == or !="));
+        assert!(parse_error("frobnicate").contains("Unknown filter function frobnicate/0"));
+        assert!(parse_error("\"open").contains("Unterminated string"));
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::Context as ErrorContext;
+use chrono::{SecondsFormat, TimeDelta};
+use reqwest::{
+    header::{HeaderMap, HeaderName, CONTENT_TYPE, COOKIE, SET_COOKIE},
+    Method, Url, Version,
+};
+use serde_json::{json, Value};
+use std::{fs, path::Path, time::Duration};
+
+use crate::{
+    parser::HttpRequest,
+    response::{millis, HttpResponse},
+};
+
+// Records executed requests and their responses as a HAR 1.2 log
+#[derive(Default)]
+pub struct HarRecorder {
+    entries: Vec<Value>,
+}
+
+impl HarRecorder {
+    // An entry for each redirect followed and one for the final response
+    pub fn record(&mut self, request: &HttpRequest, response: &HttpResponse) {
+        let timings = &response.timings;
+        // a digest challenge answered before the first hop is left out
+        let redirected: Duration = response
+            .redirects
+            .iter()
+            .map(|redirect| redirect.time)
+            .sum();
+        let mut started = response.started
+            + delta(
+                timings
+                    .redirect
+                    .unwrap_or_default()
+                    .saturating_sub(redirected),
+            );
+        let mut method = request.method.clone();
+        let mut body = request.body.as_deref();
+
+        for redirect in &response.redirects {
+            self.entries.push(json!({
+                "startedDateTime": started.to_rfc3339_opts(SecondsFormat::Millis, true),
+                "time": millis(redirect.time),
+                "request": har_request(
+                    &method,
+                    &redirect.from,
+                    request.version,
+                    &response.request_headers,
+                    body
+                ),
+                "response": {
+                    "status": redirect.status.as_u16(),
+                    "statusText": redirect.status.canonical_reason().unwrap_or_default(),
+                    "httpVersion": http_version(response.version),
+                    "cookies": [],
+                    "headers": [{ "name": "location", "value": redirect.to.as_str() }],
+                    "content": { "size": 0, "mimeType": "" },
+                    "redirectURL": redirect.to.as_str(),
+                    "headersSize": -1,
+                    "bodySize": -1,
+                },
+                "cache": {},
+                "timings": {
+                    "blocked": -1,
+                    "dns": -1,
+                    "connect": -1,
+                    "ssl": -1,
+                    "send": 0,
+                    "wait": millis(redirect.time),
+                    "receive": 0,
+                },
+            }));
+            started += delta(redirect.time);
+            // 301, 302 and 303 are followed without the body, and with GET unless it is HEAD
+            if matches!(redirect.status.as_u16(), 301..=303) {
+                body = None;
+                if method != Method::HEAD {
+                    method = Method::GET;
+                }
+            }
+        }
+
+        let location = response
+            .headers
+            .get("location")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        // bodies saved to a file are not repeated in the log
+        let mut content = json!({
+            "size": response.size,
+            "mimeType": content_type(&response.headers),
+        });
+        if response.saved.is_none() {
+            content["text"] = Value::String(response.body.clone());
+        }
+
+        self.entries.push(json!({
+            "startedDateTime": started.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "time": millis(timings.hop()),
+            "request": har_request(
+                &method,
+                &response.url,
+                request.version,
+                &response.request_headers,
+                body
+            ),
+            "response": {
+                "status": response.status.as_u16(),
+                "statusText": response.status.canonical_reason().unwrap_or_default(),
+                "httpVersion": http_version(response.version),
+                "cookies": cookies(&response.headers, SET_COOKIE),
+                "headers": headers(&response.headers),
+                "content": content,
+                "redirectURL": location,
+                "headersSize": response.headers_size,
+                "bodySize": response.size,
+            },
+            "cache": {},
+            // the TLS handshake is part of connect, -1 marks timings that do not apply
+            "timings": {
+                "blocked": -1,
+                "dns": timings.dns.map_or(-1.0, millis),
+                "connect": timings.connect.map_or(-1.0, millis),
+                "ssl": -1,
+                "send": 0,
+                "wait": millis(timings.wait()),
+                "receive": millis(timings.receive),
+            },
+        }));
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": self.entries,
+            }
+        });
+        fs::write(path, serde_json::to_string_pretty(&har)?)
+            .with_context(|| format!("Failed to write file \"{}\"", path.display()))
+    }
+}
+
+// The request of a hop, with the headers as built for the first one
+fn har_request(
+    method: &Method,
+    url: &Url,
+    version: Version,
+    headers: &HeaderMap,
+    body: Option<&[u8]>,
+) -> Value {
+    let mut request = json!({
+        "method": method.as_str(),
+        "url": url.as_str(),
+        "httpVersion": http_version(version),
+        "cookies": cookies(headers, COOKIE),
+        "headers": self::headers(headers),
+        "queryString": url
+            .query_pairs()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect::<Vec<Value>>(),
+        "headersSize": -1,
+        "bodySize": body.map_or(0, <[u8]>::len),
+    });
+    if let Some(body) = body {
+        request["postData"] = json!({
+            "mimeType": content_type(headers),
+            "text": String::from_utf8_lossy(body),
+        });
+    }
+    request
+}
+
+// Names and values of a cookie header, or of each set-cookie header
+fn cookies(headers: &HeaderMap, name: HeaderName) -> Vec<Value> {
+    // a cookie header holds several pairs, a set-cookie header one followed by attributes
+    let pairs = match name == COOKIE {
+        true => usize::MAX,
+        false => 1,
+    };
+    headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';').take(pairs))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+fn content_type(headers: &HeaderMap) -> String {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn delta(duration: Duration) -> TimeDelta {
+    TimeDelta::from_std(duration).unwrap_or_default()
+}
+
+fn headers(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            json!({
+                "name": name.as_str(),
+                "value": String::from_utf8_lossy(value.as_bytes()),
+            })
+        })
+        .collect()
+}
+
+fn http_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2.0",
+        Version::HTTP_3 => "HTTP/3.0",
+        _ => "HTTP/1.1",
+    }
+}
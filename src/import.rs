@@ -0,0 +1,176 @@
+mod curl;
+mod har;
+mod openapi;
+mod postman;
+
+use anyhow::Context as ErrorContext;
+use clap::Subcommand;
+use std::{fs, path::Path};
+
+use crate::import::{
+    curl::import_curl, har::import_har, openapi::import_openapi, postman::import_postman,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum ImportCommand {
+    /// Generate HTTP request files from an OpenAPI document
+    Openapi {
+        /// Path to an OpenAPI document
+        #[arg(name = "SPEC")]
+        spec: String,
+
+        /// Directory to write the request files to
+        #[arg(short, long, name = "DIR", default_value = ".")]
+        out: String,
+    },
+
+    /// Convert a curl command line into a HTTP request
+    Curl {
+        /// Write the request to a file instead of stdout
+        #[arg(short, long, name = "FILE")]
+        out: Option<String>,
+
+        /// The curl command, read from stdin when not given
+        #[arg(name = "COMMAND", trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Generate HTTP request files from a Postman collection
+    Postman {
+        /// Path to a Postman collection (v2.0 or v2.1)
+        #[arg(name = "COLLECTION")]
+        collection: String,
+
+        /// Path to a Postman environment to add to the environment file
+        #[arg(long, name = "ENVIRONMENT")]
+        environment: Option<String>,
+
+        /// Directory to write the request files to
+        #[arg(short, long, name = "DIR", default_value = ".")]
+        out: String,
+    },
+
+    /// Convert requests captured in a HAR file into HTTP requests
+    Har {
+        /// Path to a HAR file
+        #[arg(name = "HAR")]
+        har: String,
+
+        /// Write the requests to a file instead of stdout
+        #[arg(short, long, name = "FILE")]
+        out: Option<String>,
+    },
+}
+
+pub fn import(command: ImportCommand) -> anyhow::Result<()> {
+    match command {
+        ImportCommand::Openapi { spec, out } => import_openapi(Path::new(&spec), Path::new(&out)),
+        ImportCommand::Curl { out, command } => {
+            import_curl(&command, out.as_deref().map(Path::new))
+        }
+        ImportCommand::Postman {
+            collection,
+            environment,
+            out,
+        } => import_postman(
+            Path::new(&collection),
+            environment.as_deref().map(Path::new),
+            Path::new(&out),
+        ),
+        ImportCommand::Har { har, out } => {
+            import_har(Path::new(&har), out.as_deref().map(Path::new))
+        }
+    }
+}
+
+const BOUNDARY: &str = "----KrillFormBoundary";
+
+enum FormField {
+    Text {
+        name: String,
+        value: String,
+    },
+    File {
+        name: String,
+        path: String,
+        content_type: Option<String>,
+    },
+}
+
+// Multipart form body and its content type, lines end with CRLF when it is sent
+fn multipart_body(fields: &[FormField]) -> (String, String) {
+    let mut lines: Vec<String> = Vec::new();
+    for field in fields {
+        lines.push(format!("--{}", BOUNDARY));
+        match field {
+            FormField::Text { name, value } => {
+                lines.push(format!("Content-Disposition: form-data; name=\"{}\"", name));
+                lines.push(String::new());
+                lines.push(value.clone());
+            }
+            FormField::File {
+                name,
+                path,
+                content_type,
+            } => {
+                let file_name = Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                lines.push(format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"",
+                    name, file_name
+                ));
+                lines.push(format!(
+                    "Content-Type: {}",
+                    content_type
+                        .as_deref()
+                        .unwrap_or("application/octet-stream")
+                ));
+                lines.push(String::new());
+                lines.push(include(path));
+            }
+        }
+    }
+    lines.push(format!("--{}--", BOUNDARY));
+    (
+        format!("multipart/form-data; boundary={}", BOUNDARY),
+        lines.join("\n"),
+    )
+}
+
+// File content is sent as it is, without rendering it as a template
+fn include(path: &str) -> String {
+    format!("< {}", path)
+}
+
+// Literal braces must not be taken as template expressions
+fn escape(value: &str) -> String {
+    value.replace("{{", "\\{{")
+}
+
+fn write_file(path: &Path, content: &str) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory \"{}\"", parent.display()))?;
+    }
+    fs::write(path, content)
+        .with_context(|| format!("Failed to write file \"{}\"", path.display()))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+// File name friendly version of a name, e.g. "User Accounts" -> "user-accounts"
+fn slug(name: &str) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_lowercase(),
+            false => '-',
+        })
+        .collect();
+    slug.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
@@ -0,0 +1,454 @@
+use anyhow::Context as ErrorContext;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+use std::{
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::import::{escape, include, multipart_body, write_file, FormField};
+
+// Characters not allowed in a URL, existing escapes and reserved characters are kept
+const URL_INVALID: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'<')
+    .add(b'>')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+// Options that take a value but have no equivalent in a request file
+const IGNORED_WITH_VALUE: [&str; 20] = [
+    "-o",
+    "--output",
+    "-m",
+    "--max-time",
+    "--connect-timeout",
+    "-x",
+    "--proxy",
+    "-w",
+    "--write-out",
+    "-c",
+    "--cookie-jar",
+    "--retry",
+    "--cacert",
+    "-E",
+    "--cert",
+    "--key",
+    "--resolve",
+    "-r",
+    "--range",
+    "--max-redirs",
+];
+
+#[derive(Default)]
+struct CurlRequest {
+    method: Option<String>,
+    url: Option<String>,
+    headers: Vec<(String, String)>,
+    data: Vec<String>,
+    // some data is read from a file with @file
+    data_file: bool,
+    form: Vec<String>,
+    // file sent as the body with -T
+    upload: Option<String>,
+    get: bool,
+    head: bool,
+    insecure: bool,
+}
+
+pub fn import_curl(command: &[String], out: Option<&Path>) -> anyhow::Result<()> {
+    let args = match command {
+        [] => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .context("Failed to read curl command from stdin")?;
+            split_command(&input)?
+        }
+        [single] => split_command(single)?,
+        args => args.to_vec(),
+    };
+
+    let request = parse_curl(&args)?;
+    let content = to_http(&request)?;
+
+    match out {
+        Some(path) => write_file(path, &content),
+        None => {
+            print!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+fn parse_curl(args: &[String]) -> anyhow::Result<CurlRequest> {
+    let mut request = CurlRequest::default();
+    let mut args = args.iter().map(String::as_str);
+    let mut positional = false;
+
+    while let Some(arg) = args.next() {
+        if positional || !arg.starts_with('-') || arg == "-" {
+            if arg != "curl" || request.url.is_some() {
+                request.url = Some(arg.to_string());
+            }
+            continue;
+        }
+        if arg == "--" {
+            positional = true;
+            continue;
+        }
+
+        // short flags can be combined and carry their value, e.g. -sSL or -XPOST
+        let options: Vec<(String, Option<String>)> = match arg.starts_with("--") {
+            true => vec![(arg.to_string(), None)],
+            false => {
+                let mut options = Vec::new();
+                let flags: Vec<char> = arg.chars().skip(1).collect();
+                for (index, flag) in flags.iter().enumerate() {
+                    let option = format!("-{}", flag);
+                    if takes_value(&option) {
+                        let rest: String = flags[index + 1..].iter().collect();
+                        options.push((option, Some(rest).filter(|rest| !rest.is_empty())));
+                        break;
+                    }
+                    options.push((option, None));
+                }
+                options
+            }
+        };
+
+        for (option, attached) in options {
+            let mut value = || -> anyhow::Result<String> {
+                match &attached {
+                    Some(value) => Ok(value.clone()),
+                    None => args
+                        .next()
+                        .map(String::from)
+                        .with_context(|| format!("Option {} requires a value", option)),
+                }
+            };
+            match option.as_str() {
+                "-X" | "--request" => request.method = Some(value()?.to_uppercase()),
+                "-H" | "--header" => {
+                    let header = value()?;
+                    if let Some((name, value)) = header.split_once(':') {
+                        let value = value.trim();
+                        if !value.is_empty() {
+                            request
+                                .headers
+                                .push((name.trim().to_string(), value.to_string()));
+                        }
+                    }
+                }
+                "-d" | "--data" | "--data-ascii" => {
+                    let data = value()?;
+                    request.data.push(match data.strip_prefix('@') {
+                        Some(file) => {
+                            request.data_file = true;
+                            include(file)
+                        }
+                        None => escape(&data.replace(['\r', '\n'], "")),
+                    });
+                }
+                "--data-binary" => {
+                    let data = value()?;
+                    request.data.push(match data.strip_prefix('@') {
+                        Some(file) => {
+                            request.data_file = true;
+                            include(file)
+                        }
+                        None => escape(&data),
+                    });
+                }
+                "--data-raw" => request.data.push(escape(&value()?)),
+                "--data-urlencode" => request.data.push(url_encode_data(&value()?)),
+                "--json" => {
+                    let data = value()?;
+                    request.data.push(match data.strip_prefix('@') {
+                        Some(file) => {
+                            request.data_file = true;
+                            include(file)
+                        }
+                        None => escape(&data),
+                    });
+                    set_default_header(&mut request, "Content-Type", "application/json");
+                    set_default_header(&mut request, "Accept", "application/json");
+                }
+                "-F" | "--form" | "--form-string" => request.form.push(value()?),
+                "-u" | "--user" => {
+                    let user = value()?;
+                    let credentials = match user.contains(':') {
+                        true => user,
+                        false => format!("{}:", user),
+                    };
+                    request.headers.push((
+                        "Authorization".to_string(),
+                        format!("Basic {}", STANDARD.encode(credentials)),
+                    ));
+                }
+                "-A" | "--user-agent" => request.headers.push(("User-Agent".to_string(), value()?)),
+                "-e" | "--referer" => request.headers.push(("Referer".to_string(), value()?)),
+                "-b" | "--cookie" => {
+                    // without a "=" the value is a cookie file name
+                    let cookie = value()?;
+                    if cookie.contains('=') {
+                        request.headers.push(("Cookie".to_string(), cookie));
+                    }
+                }
+                "--url" => request.url = Some(value()?),
+                "-T" | "--upload-file" => request.upload = Some(value()?),
+                "-k" | "--insecure" => request.insecure = true,
+                "-G" | "--get" => request.get = true,
+                "-I" | "--head" => request.head = true,
+                option if IGNORED_WITH_VALUE.contains(&option) => {
+                    value()?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(request)
+}
+
+fn to_http(request: &CurlRequest) -> anyhow::Result<String> {
+    let mut url = request
+        .url
+        .clone()
+        .context("The curl command should contain an URL")?;
+    if !url.contains("://") {
+        url = format!("http://{}", url);
+    }
+
+    // a file reference is a body line of its own, it cannot be joined with other data
+    if request.data_file && request.get {
+        anyhow::bail!("Data from a file cannot be sent in the query with -G");
+    }
+    if request.data_file && request.data.len() > 1 {
+        anyhow::bail!("Data from a file cannot be combined with other data");
+    }
+    if request.upload.is_some() && !(request.data.is_empty() && request.form.is_empty()) {
+        anyhow::bail!("A file uploaded with -T cannot be combined with data or form fields");
+    }
+
+    let mut headers = request.headers.clone();
+    let mut body: Option<String> = None;
+
+    if let Some(file) = &request.upload {
+        if file == "-" || file == "." {
+            anyhow::bail!("Uploading from stdin with -T {} is not supported", file);
+        }
+        // curl appends the file name to URLs without one
+        if url.ends_with('/') {
+            let name = Path::new(file).file_name().unwrap_or_default();
+            url = format!("{}{}", url, name.to_string_lossy());
+        }
+        body = Some(include(file));
+    } else if request.get {
+        if !request.data.is_empty() {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            url = format!("{}{}{}", url, separator, request.data.join("&"));
+        }
+    } else if !request.form.is_empty() {
+        let (content_type, multipart) = multipart_body(&form_fields(&request.form));
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Type"));
+        headers.push(("Content-Type".to_string(), content_type));
+        body = Some(multipart);
+    } else if !request.data.is_empty() {
+        if !has_header(&headers, "Content-Type") {
+            headers.push((
+                "Content-Type".to_string(),
+                "application/x-www-form-urlencoded".to_string(),
+            ));
+        }
+        body = Some(request.data.join("&"));
+    }
+
+    let method = match &request.method {
+        Some(method) => method.clone(),
+        None if request.head => "HEAD".to_string(),
+        None if request.upload.is_some() => "PUT".to_string(),
+        None if body.is_some() => "POST".to_string(),
+        None => "GET".to_string(),
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    if request.insecure {
+        lines.push("# @insecure".to_string());
+    }
+    let url = utf8_percent_encode(&url, URL_INVALID);
+    lines.push(format!("{} {} HTTP/1.1", method, url));
+    for (name, value) in headers {
+        lines.push(format!("{}: {}", name, escape(&value)));
+    }
+    if let Some(body) = body {
+        lines.push(String::new());
+        lines.push(body);
+    }
+    lines.push(String::new());
+
+    Ok(lines.join("\n"))
+}
+
+// Form fields as given to -F, e.g. name=bob, file=@photo.png;type=image/png or text=<notes.txt
+fn form_fields(form: &[String]) -> Vec<FormField> {
+    form.iter()
+        .map(|field| {
+            let (name, value) = field.split_once('=').unwrap_or((field, ""));
+            let name = name.to_string();
+            match (value.strip_prefix('@'), value.strip_prefix('<')) {
+                (Some(file), _) => {
+                    let mut parts = file.split(';');
+                    let path = parts.next().unwrap_or_default().to_string();
+                    let content_type = parts
+                        .find_map(|part| part.trim().strip_prefix("type="))
+                        .map(String::from);
+                    FormField::File {
+                        name,
+                        path,
+                        content_type,
+                    }
+                }
+                (None, Some(path)) => FormField::Text {
+                    name,
+                    value: include(path),
+                },
+                (None, None) => FormField::Text {
+                    name,
+                    value: escape(value),
+                },
+            }
+        })
+        .collect()
+}
+
+fn takes_value(option: &str) -> bool {
+    matches!(
+        option,
+        "-X" | "-H" | "-d" | "-F" | "-u" | "-A" | "-e" | "-b" | "-T"
+    ) || IGNORED_WITH_VALUE.contains(&option)
+}
+
+fn has_header(headers: &[(String, String)], name: &str) -> bool {
+    headers
+        .iter()
+        .any(|(key, _)| key.eq_ignore_ascii_case(name))
+}
+
+fn set_default_header(request: &mut CurlRequest, name: &str, value: &str) {
+    if !has_header(&request.headers, name) {
+        request.headers.push((name.to_string(), value.to_string()));
+    }
+}
+
+fn url_encode_data(data: &str) -> String {
+    let encode = |value: &str| utf8_percent_encode(value, NON_ALPHANUMERIC).to_string();
+    match data.split_once('=') {
+        Some(("", value)) => encode(value),
+        Some((name, value)) => format!("{}={}", name, encode(value)),
+        None => encode(data),
+    }
+}
+
+// Split a shell command line into arguments, handling the quoting
+// styles browsers use when copying requests as curl
+fn split_command(input: &str) -> anyhow::Result<Vec<String>> {
+    let mut args: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => anyhow::bail!("Unterminated quote in curl command"),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => anyhow::bail!("Unterminated quote in curl command"),
+                        },
+                        Some(c) => current.push(c),
+                        None => anyhow::bail!("Unterminated quote in curl command"),
+                    }
+                }
+            }
+            // ANSI-C quoting, e.g. $'{"a":"b\n"}'
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => current.push('\n'),
+                            Some('t') => current.push('\t'),
+                            Some('r') => current.push('\r'),
+                            Some(c @ ('x' | 'u')) => {
+                                let len = if c == 'x' { 2 } else { 4 };
+                                let hex: String = (0..len).filter_map(|_| chars.next()).collect();
+                                let decoded = u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(char::from_u32)
+                                    .with_context(|| format!("Invalid escape \\{}{}", c, hex))?;
+                                current.push(decoded);
+                            }
+                            Some(c) => current.push(c),
+                            None => anyhow::bail!("Unterminated quote in curl command"),
+                        },
+                        Some(c) => current.push(c),
+                        None => anyhow::bail!("Unterminated quote in curl command"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // line continuation
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(c) => {
+                    in_arg = true;
+                    current.push(c);
+                }
+                None => {}
+            },
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
+}
@@ -0,0 +1,129 @@
+use anyhow::Context as ErrorContext;
+use serde_json::Value;
+use std::{collections::BTreeSet, fs, path::Path};
+
+use crate::import::{escape, slug, write_file};
+
+// Headers the client sets itself or that only exist in HTTP/2 captures
+const SKIPPED_HEADERS: [&str; 4] = ["host", "content-length", "connection", "accept-encoding"];
+
+pub fn import_har(har_path: &Path, out: Option<&Path>) -> anyhow::Result<()> {
+    let content = fs::read_to_string(har_path)
+        .with_context(|| format!("Failed to open file \"{}\"", har_path.display()))?;
+    let har: Value = serde_json::from_str(&content)
+        .with_context(|| format!("File should be valid JSON \"{}\"", har_path.display()))?;
+    let entries = har["log"]["entries"]
+        .as_array()
+        .context("HAR file should have log entries")?;
+
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    let requests: Vec<String> = entries
+        .iter()
+        .map(|entry| &entry["request"])
+        // skip data URLs and other captures that are not HTTP requests
+        .filter(|request| {
+            request["url"]
+                .as_str()
+                .is_some_and(|url| url.starts_with("http://") || url.starts_with("https://"))
+        })
+        .map(|request| request_block(request, &mut names))
+        .collect();
+
+    let content = requests.join("\n###\n\n");
+    match out {
+        Some(path) => write_file(path, &content),
+        None => {
+            print!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+fn request_block(request: &Value, names: &mut BTreeSet<String>) -> String {
+    let method = request["method"].as_str().unwrap_or("GET").to_uppercase();
+    let url = request["url"].as_str().unwrap_or_default();
+
+    let mut lines: Vec<String> = Vec::new();
+    lines.push(format!("# @name {}", unique_name(&method, url, names)));
+    // the client negotiates the protocol, captures from HTTP/2 are sent as HTTP/1.1
+    lines.push(format!("{} {} HTTP/1.1", method, escape(url)));
+
+    let mut has_cookie = false;
+    let mut has_content_type = false;
+    for header in request["headers"].as_array().into_iter().flatten() {
+        let name = header["name"].as_str().unwrap_or_default();
+        let value = header["value"].as_str().unwrap_or_default();
+        if name.starts_with(':') || SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()) {
+            continue;
+        }
+        has_cookie |= name.eq_ignore_ascii_case("cookie");
+        has_content_type |= name.eq_ignore_ascii_case("content-type");
+        lines.push(format!("{}: {}", name, escape(value)));
+    }
+
+    // some tools only list cookies separately from the headers
+    let cookies: Vec<String> = request["cookies"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|cookie| {
+            Some(format!(
+                "{}={}",
+                cookie["name"].as_str()?,
+                cookie["value"].as_str()?
+            ))
+        })
+        .collect();
+    if !has_cookie && !cookies.is_empty() {
+        lines.push(format!("Cookie: {}", escape(&cookies.join("; "))));
+    }
+
+    let post_data = &request["postData"];
+    let body = match (post_data["text"].as_str(), post_data["params"].as_array()) {
+        (Some(text), _) => Some(escape(text)),
+        (None, Some(params)) => Some(
+            params
+                .iter()
+                .map(|param| {
+                    format!(
+                        "{}={}",
+                        param["name"].as_str().unwrap_or_default(),
+                        param["value"].as_str().unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("&"),
+        ),
+        (None, None) => None,
+    };
+    if let Some(body) = body.filter(|body| !body.is_empty()) {
+        if let (Some(mime_type), false) = (post_data["mimeType"].as_str(), has_content_type) {
+            lines.push(format!("Content-Type: {}", mime_type));
+        }
+        lines.push(String::new());
+        lines.push(body);
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// Requests are named after their method and last path segment, e.g. get-users
+fn unique_name(method: &str, url: &str, names: &mut BTreeSet<String>) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segment = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|segment| !segment.contains(':'))
+        .unwrap_or_default();
+    let base = slug(&format!("{} {}", method, segment));
+    let mut name = base.clone();
+    let mut index = 2;
+    while names.contains(&name) {
+        name = format!("{}-{}", base, index);
+        index += 1;
+    }
+    names.insert(name.clone());
+    name
+}
@@ -0,0 +1,164 @@
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{
+    import::{slug, write_file},
+    openapi::OpenApiSpec,
+    schema::{sample_scalar, sample_template},
+};
+
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+pub fn import_openapi(spec_path: &Path, out: &Path) -> anyhow::Result<()> {
+    let spec = OpenApiSpec::load(spec_path)?;
+    let document = spec.document();
+
+    // requests are grouped into one file per tag
+    let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (path, path_item) in document["paths"].as_object().into_iter().flatten() {
+        let path_item = spec.resolve(path_item);
+        for method in METHODS {
+            let Some(operation) = path_item.get(method) else {
+                continue;
+            };
+            let tag = operation["tags"][0].as_str().unwrap_or("default");
+            let request = request_block(&spec, path, method, path_item, operation);
+            files.entry(slug(tag)).or_default().push(request);
+        }
+    }
+
+    let title = document["info"]["title"].as_str().unwrap_or("OpenAPI");
+    let base_url = base_url(document);
+    for (name, requests) in files {
+        let content = format!(
+            "# Generated from {}\n@baseUrl = {}\n\n{}",
+            title,
+            base_url,
+            requests.join("\n###\n\n")
+        );
+        write_file(&out.join(format!("{}.http", name)), &content)?;
+    }
+
+    Ok(())
+}
+
+fn request_block(
+    spec: &OpenApiSpec,
+    path: &str,
+    method: &str,
+    path_item: &Value,
+    operation: &Value,
+) -> String {
+    let document = spec.document();
+    let mut lines: Vec<String> = Vec::new();
+
+    if let Some(summary) = operation["summary"].as_str() {
+        lines.push(format!("# {}", summary.lines().next().unwrap_or_default()));
+    }
+    let name = match operation["operationId"].as_str() {
+        Some(operation_id) => operation_id.to_string(),
+        None => slug(&format!("{} {}", method, path)),
+    };
+    lines.push(format!("# @name {}", name));
+
+    let parameters: Vec<&Value> = [path_item, operation]
+        .into_iter()
+        .filter_map(|item| item["parameters"].as_array())
+        .flatten()
+        .map(|parameter| spec.resolve(parameter))
+        .collect();
+
+    let mut query: Vec<String> = Vec::new();
+    let mut headers: Vec<String> = Vec::new();
+    for parameter in parameters {
+        let name = parameter["name"].as_str().unwrap_or_default();
+        let required = parameter["required"].as_bool().unwrap_or(false);
+        let value = match parameter.get("example") {
+            Some(Value::String(example)) => example.clone(),
+            Some(example) => example.to_string(),
+            None => sample_scalar(document, &parameter["schema"]),
+        };
+        match parameter["in"].as_str() {
+            // path parameters become request variables that can be overridden
+            Some("path") => lines.push(format!("@{} = {}", name, value)),
+            Some("query") if required => query.push(format!("{}={}", name, value)),
+            Some("header") if required => headers.push(format!("{}: {}", name, value)),
+            _ => {}
+        }
+    }
+
+    let mut url = format!(
+        "{{{{baseUrl}}}}{}",
+        path.replace('{', "{{").replace('}', "}}")
+    );
+    if !query.is_empty() {
+        url = format!("{}?{}", url, query.join("&"));
+    }
+    lines.push(format!("{} {} HTTP/1.1", method.to_uppercase(), url));
+
+    let accept = operation["responses"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(status, _)| status.starts_with('2'))
+        .filter_map(|(_, response)| spec.resolve(response)["content"].as_object())
+        .find_map(|content| content.keys().next());
+    if let Some(accept) = accept {
+        lines.push(format!("Accept: {}", accept));
+    }
+    lines.extend(headers);
+
+    let content = operation
+        .get("requestBody")
+        .map(|request_body| &spec.resolve(request_body)["content"])
+        .and_then(Value::as_object);
+    if let Some(content) = content {
+        let media = content
+            .iter()
+            .find(|(content_type, _)| content_type.as_str() == "application/json")
+            .or_else(|| {
+                content
+                    .iter()
+                    .find(|(content_type, _)| content_type.ends_with("+json"))
+            })
+            .or_else(|| content.iter().next());
+        if let Some((content_type, media)) = media {
+            lines.push(format!("Content-Type: {}", content_type));
+            lines.push(String::new());
+            lines.push(sample_body(document, media));
+        }
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn sample_body(document: &Value, media: &Value) -> String {
+    let example = media.get("example").or_else(|| {
+        media["examples"]
+            .as_object()
+            .and_then(|examples| examples.values().next())
+            .map(|example| &example["value"])
+    });
+    match example {
+        Some(Value::String(example)) => example.clone(),
+        Some(example) => serde_json::to_string_pretty(example).unwrap_or_default(),
+        None => sample_template(document, &media["schema"]),
+    }
+}
+
+fn base_url(document: &Value) -> String {
+    let server = &document["servers"][0];
+    let mut url = server["url"].as_str().unwrap_or_default().to_string();
+    // server variables are replaced with their default values
+    for (name, variable) in server["variables"].as_object().into_iter().flatten() {
+        let default = variable["default"].as_str().unwrap_or_default();
+        url = url.replace(&format!("{{{}}}", name), default);
+    }
+    match url.contains("://") {
+        true => url.trim_end_matches('/').to_string(),
+        false => format!("http://localhost{}", url.trim_end_matches('/')),
+    }
+}
@@ -0,0 +1,453 @@
+use anyhow::Context as ErrorContext;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    context::{ENV_FILE, SHARED_ENV},
+    import::{include, multipart_body, slug, write_file, FormField},
+};
+
+// Postman dynamic variables and their equivalent helpers
+const DYNAMIC_VARIABLES: [(&str, &str); 55] = [
+    ("$guid", "$randomGUID"),
+    ("$timestamp", "$timestamp"),
+    ("$isoTimestamp", "$timestamp format='iso8601'"),
+    ("$randomUUID", "$randomUUIDv4"),
+    ("$randomInt", "$randomInt"),
+    ("$randomBoolean", "$randomBoolean"),
+    ("$randomEmail", "$randomSafeEmail"),
+    ("$randomExampleEmail", "$randomSafeEmail"),
+    ("$randomUserName", "$randomUsername"),
+    ("$randomPassword", "$randomPassword"),
+    ("$randomFirstName", "$randomFirstName"),
+    ("$randomLastName", "$randomLastName"),
+    ("$randomFullName", "$randomFullName"),
+    ("$randomNamePrefix", "$randomNamePrefix"),
+    ("$randomNameSuffix", "$randomNameSuffix"),
+    ("$randomJobTitle", "$randomJobTitle"),
+    ("$randomJobArea", "$randomJobField"),
+    ("$randomJobType", "$randomJobPosition"),
+    ("$randomIP", "$randomIPv4"),
+    ("$randomIPV6", "$randomIPv6"),
+    ("$randomMACAddress", "$randomMACAddress"),
+    ("$randomUserAgent", "$randomUserAgent"),
+    ("$randomDomainSuffix", "$randomDomainSuffix"),
+    ("$randomColor", "$randomHexColor"),
+    ("$randomHexColor", "$randomHexColor"),
+    ("$randomCity", "$randomCityName"),
+    ("$randomCountry", "$randomCountryName"),
+    ("$randomCountryCode", "$randomCountryCode"),
+    ("$randomStreetName", "$randomStreetName"),
+    ("$randomLatitude", "$randomLatitude"),
+    ("$randomLongitude", "$randomLongitude"),
+    ("$randomCompanyName", "$randomCompanyName"),
+    ("$randomCompanySuffix", "$randomCompanySuffix"),
+    ("$randomCatchPhrase", "$randomCatchPhrase"),
+    ("$randomBs", "$randomBS"),
+    ("$randomBsAdjective", "$randomBSAdj"),
+    ("$randomBsBuzz", "$randomBSVerb"),
+    ("$randomBsNoun", "$randomBSNoun"),
+    ("$randomCurrencyCode", "$randomCurrencyCode"),
+    ("$randomCurrencyName", "$randomCurrencyName"),
+    ("$randomCurrencySymbol", "$randomCurrencySymbol"),
+    ("$randomWord", "$randomWord"),
+    ("$randomWords", "$randomWord count=3"),
+    ("$randomLoremWord", "$randomWord"),
+    ("$randomLoremWords", "$randomWord count=3"),
+    ("$randomLoremSentence", "$randomSentence"),
+    ("$randomLoremSentences", "$randomSentence count=3"),
+    ("$randomLoremParagraph", "$randomParagraph"),
+    ("$randomFileName", "$randomFileName"),
+    ("$randomFileExt", "$randomFileExtension"),
+    ("$randomFilePath", "$randomFilePath"),
+    ("$randomDirectoryPath", "$randomDirPath"),
+    ("$randomMimeType", "$randomMimeType"),
+    ("$randomSemver", "$randomSemver"),
+    ("$randomBankAccountBic", "$randomBIC"),
+];
+
+pub fn import_postman(
+    collection_path: &Path,
+    environment_path: Option<&Path>,
+    out: &Path,
+) -> anyhow::Result<()> {
+    let collection = read_json(collection_path)?;
+    if collection["item"].as_array().is_none() {
+        anyhow::bail!("Postman collection should have items");
+    }
+
+    write_items(
+        &collection["item"],
+        &collection["auth"],
+        out,
+        &mut BTreeSet::new(),
+    )?;
+
+    // collection variables are shared, environment variables go to a named environment
+    let mut environments = match fs::read_to_string(out.join(ENV_FILE)) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|_| json!({})),
+        Err(_) => json!({}),
+    };
+    let shared = variables(&collection["variable"], "disabled", false);
+    if !shared.is_empty() {
+        merge_environment(&mut environments, SHARED_ENV, shared);
+    }
+    if let Some(path) = environment_path {
+        let environment = read_json(path)?;
+        let name = environment["name"].as_str().unwrap_or("default");
+        let values = variables(&environment["values"], "enabled", true);
+        merge_environment(&mut environments, name, values);
+    }
+    if environments
+        .as_object()
+        .is_some_and(|envs| !envs.is_empty())
+    {
+        let content = serde_json::to_string_pretty(&environments)?;
+        write_file(&out.join(ENV_FILE), &format!("{}\n", content))?;
+    }
+
+    Ok(())
+}
+
+fn read_json(path: &Path) -> anyhow::Result<Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to open file \"{}\"", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("File should be valid JSON \"{}\"", path.display()))
+}
+
+// Folders become directories, requests become files
+fn write_items(
+    items: &Value,
+    auth: &Value,
+    dir: &Path,
+    names: &mut BTreeSet<PathBuf>,
+) -> anyhow::Result<()> {
+    for item in items.as_array().into_iter().flatten() {
+        let name = item["name"].as_str().unwrap_or("request");
+        // auth is inherited from the enclosing folder or collection
+        let auth = match item["auth"].is_null() {
+            true => auth,
+            false => &item["auth"],
+        };
+        if item.get("item").is_some() {
+            write_items(&item["item"], auth, &dir.join(slug(name)), names)?;
+            continue;
+        }
+        let Some(request) = item.get("request") else {
+            continue;
+        };
+        let content = request_file(name, request, auth);
+        write_file(&unique_path(dir, &slug(name), names), &content)?;
+    }
+    Ok(())
+}
+
+fn unique_path(dir: &Path, name: &str, names: &mut BTreeSet<PathBuf>) -> PathBuf {
+    let mut path = dir.join(format!("{}.http", name));
+    let mut index = 2;
+    while names.contains(&path) {
+        path = dir.join(format!("{}-{}.http", name, index));
+        index += 1;
+    }
+    names.insert(path.clone());
+    path
+}
+
+fn request_file(name: &str, request: &Value, auth: &Value) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    // a request can be just the url
+    let request = match request {
+        Value::String(url) => &json!({ "method": "GET", "url": url }),
+        request => request,
+    };
+    let auth = match request["auth"].is_null() {
+        true => auth,
+        false => &request["auth"],
+    };
+
+    let description = request["description"]
+        .as_str()
+        .or_else(|| request["description"]["content"].as_str());
+    for line in description.into_iter().flat_map(str::lines) {
+        lines.push(format!("# {}", line).trim_end().to_string());
+    }
+    lines.push(format!("# @name {}", slug(name)));
+
+    // path variables, e.g. /users/:id
+    for (key, value) in variables(&request["url"]["variable"], "disabled", false) {
+        lines.push(format!("@{} = {}", key, value));
+    }
+
+    let raw_url = match &request["url"] {
+        Value::String(url) => url.clone(),
+        url => url["raw"].as_str().unwrap_or_default().to_string(),
+    };
+    let mut url = convert_template(&path_variables(&raw_url));
+    let method = request["method"].as_str().unwrap_or("GET").to_uppercase();
+
+    let mut headers: Vec<(String, String)> = enabled(&request["header"])
+        .map(|header| {
+            (
+                header["key"].as_str().unwrap_or_default().to_string(),
+                convert_template(&value_string(&header["value"])),
+            )
+        })
+        .collect();
+
+    match auth["type"].as_str() {
+        Some("bearer") => {
+            let token = auth_value(auth, "bearer", "token");
+            headers.push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+        Some("basic") => {
+            let username = auth_value(auth, "basic", "username");
+            let password = auth_value(auth, "basic", "password");
+            match username.contains("{{") || password.contains("{{") {
+                true => eprintln!(
+                    "Basic auth with variables is not converted in \"{}\", set the Authorization header manually",
+                    name
+                ),
+                false => {
+                    let credentials = STANDARD.encode(format!("{}:{}", username, password));
+                    headers.push(("Authorization".to_string(), format!("Basic {}", credentials)));
+                }
+            }
+        }
+        Some("apikey") => {
+            let key = auth_value(auth, "apikey", "key");
+            let value = auth_value(auth, "apikey", "value");
+            match auth_value(auth, "apikey", "in").as_str() {
+                "query" => {
+                    let separator = if url.contains('?') { '&' } else { '?' };
+                    url = format!("{}{}{}={}", url, separator, key, value);
+                }
+                _ => headers.push((key, value)),
+            }
+        }
+        Some("noauth") | None => {}
+        Some(other) => eprintln!("Auth type \"{}\" is not converted in \"{}\"", other, name),
+    }
+
+    let body = request_body(&request["body"]);
+    if let Some((content_type, _)) = &body {
+        let has_content_type = headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case("Content-Type"));
+        if let (Some(content_type), false) = (content_type, has_content_type) {
+            headers.push(("Content-Type".to_string(), content_type.clone()));
+        }
+    }
+
+    lines.push(format!("{} {} HTTP/1.1", method, url));
+    for (key, value) in headers {
+        lines.push(format!("{}: {}", key, value));
+    }
+    if let Some((_, body)) = body {
+        lines.push(String::new());
+        lines.push(body);
+    }
+    lines.push(String::new());
+
+    lines.join("\n")
+}
+
+// Request body and its content type
+fn request_body(body: &Value) -> Option<(Option<String>, String)> {
+    match body["mode"].as_str()? {
+        "raw" => {
+            let raw = body["raw"].as_str().filter(|raw| !raw.is_empty())?;
+            let content_type = match body["options"]["raw"]["language"].as_str() {
+                Some("json") => Some("application/json"),
+                Some("xml") => Some("application/xml"),
+                Some("html") => Some("text/html"),
+                Some("javascript") => Some("application/javascript"),
+                Some("text") => Some("text/plain"),
+                _ => None,
+            };
+            Some((content_type.map(String::from), convert_template(raw)))
+        }
+        "urlencoded" => {
+            let pairs: Vec<String> = enabled(&body["urlencoded"])
+                .map(|pair| {
+                    format!(
+                        "{}={}",
+                        pair["key"].as_str().unwrap_or_default(),
+                        convert_template(&value_string(&pair["value"]))
+                    )
+                })
+                .collect();
+            Some((
+                Some("application/x-www-form-urlencoded".to_string()),
+                pairs.join("&"),
+            ))
+        }
+        "formdata" => {
+            let fields: Vec<FormField> = enabled(&body["formdata"])
+                .flat_map(|field| {
+                    let name = field["key"].as_str().unwrap_or_default().to_string();
+                    match field["type"].as_str() {
+                        // one part per selected file, src is a path or a list of them
+                        Some("file") => {
+                            let content_type = field["contentType"].as_str().map(String::from);
+                            file_paths(&field["src"])
+                                .into_iter()
+                                .map(|path| FormField::File {
+                                    name: name.clone(),
+                                    path,
+                                    content_type: content_type.clone(),
+                                })
+                                .collect()
+                        }
+                        _ => vec![FormField::Text {
+                            name,
+                            value: convert_template(&value_string(&field["value"])),
+                        }],
+                    }
+                })
+                .collect();
+            let (content_type, body) = multipart_body(&fields);
+            Some((Some(content_type), body))
+        }
+        "graphql" => {
+            let query = body["graphql"]["query"].as_str().unwrap_or_default();
+            let variables: Value = body["graphql"]["variables"]
+                .as_str()
+                .and_then(|variables| serde_json::from_str(variables).ok())
+                .unwrap_or(Value::Null);
+            let body = json!({ "query": query, "variables": variables });
+            Some((
+                Some("application/json".to_string()),
+                convert_template(&serde_json::to_string_pretty(&body).unwrap_or_default()),
+            ))
+        }
+        "file" => {
+            let path = file_paths(&body["file"]["src"]).into_iter().next()?;
+            Some((None, include(&path)))
+        }
+        _ => None,
+    }
+}
+
+fn enabled(values: &Value) -> impl Iterator<Item = &Value> {
+    values
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|value| value["disabled"] != Value::Bool(true))
+}
+
+fn file_paths(src: &Value) -> Vec<String> {
+    let paths = match src {
+        Value::Array(paths) => paths.iter().filter_map(Value::as_str).collect(),
+        src => src.as_str().into_iter().collect::<Vec<&str>>(),
+    };
+    paths
+        .into_iter()
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+// Key value pairs, skipping disabled ones. Collections mark them with
+// "disabled": true, environments with "enabled": false
+fn variables(values: &Value, flag: &str, expected: bool) -> Vec<(String, String)> {
+    values
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|value| value[flag].as_bool().unwrap_or(expected) == expected)
+        .filter_map(|value| {
+            let key = value["key"].as_str()?;
+            Some((
+                key.to_string(),
+                convert_template(&value_string(&value["value"])),
+            ))
+        })
+        .collect()
+}
+
+fn merge_environment(environments: &mut Value, name: &str, values: Vec<(String, String)>) {
+    let Some(environments) = environments.as_object_mut() else {
+        return;
+    };
+    let environment = environments
+        .entry(name.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Some(environment) = environment.as_object_mut() {
+        for (key, value) in values {
+            environment.insert(key, Value::String(value));
+        }
+    }
+}
+
+fn auth_value(auth: &Value, kind: &str, key: &str) -> String {
+    let value = auth[kind]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|entry| entry["key"] == key)
+        .map(|entry| value_string(&entry["value"]))
+        .unwrap_or_default();
+    convert_template(&value)
+}
+
+// Postman path variables, e.g. /users/:id -> /users/{{id}}
+fn path_variables(url: &str) -> String {
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (url, None),
+    };
+    let path: Vec<String> = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) if !name.is_empty() => format!("{{{{{}}}}}", name),
+            _ => segment.to_string(),
+        })
+        .collect();
+    match query {
+        Some(query) => format!("{}?{}", path.join("/"), query),
+        None => path.join("/"),
+    }
+}
+
+// Map Postman dynamic variables onto helpers, e.g. {{$guid}} -> {{$randomGUID}}
+fn convert_template(template: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{$") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = rest[start + 2..start + end].trim();
+        match DYNAMIC_VARIABLES
+            .iter()
+            .find(|(postman, _)| *postman == name)
+        {
+            Some((_, helper)) => out.push_str(&format!("{{{{{}}}}}", helper)),
+            None => {
+                eprintln!("Postman dynamic variable {} has no equivalent", name);
+                out.push_str(&rest[start..start + end + 2]);
+            }
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
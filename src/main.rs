@@ -1,55 +1,323 @@
+mod auth;
+mod aws;
+mod client;
 mod context;
+mod cookies;
+mod crypto;
+mod dates;
+mod export;
+mod filter;
+mod har;
+mod import;
+mod oauth2;
+mod openapi;
 mod output;
 mod parser;
+mod pretty;
+mod random;
+mod response;
+mod schema;
+mod transform;
 
+use crate::client::{Clients, TraceLog};
 use crate::context::RequestContext;
-use crate::output::output_http;
-use crate::parser::parse_http_file;
+use crate::cookies::CookieJar;
+use crate::export::{export, ExportCommand};
+use crate::filter::Filter;
+use crate::har::HarRecorder;
+use crate::import::{import, ImportCommand};
+use crate::openapi::OpenApiSpec;
+use crate::output::{
+    output_connection, output_contract_violations, output_http, output_raw_request,
+    output_schema_violations, ColorChoice, OutputMode, OutputOptions,
+};
+use crate::parser::{parse_http_file, OutputFile};
+use crate::response::HttpResponse;
+use crate::schema::validate_json;
 
-use clap::Parser;
-use reqwest::blocking::Client;
+use clap::{Parser, Subcommand};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 #[derive(Clone, Debug)]
 struct KeyValue(String, String);
 
 /// A small and fast CLI for testing REST APIs
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to a HTTP request file
-    #[arg(short, long, name = "FILE")]
-    file: String,
+    #[arg(short, long, name = "FILE", required = true)]
+    file: Option<String>,
 
     /// Parameters to be passed to the request
     /// in the form of key=value
     #[arg(short, long, name = "key=value", value_parser = parse_param)]
     param: Vec<KeyValue>,
 
+    /// Name of the environment to use from the environment file
+    #[arg(short, long, name = "ENV")]
+    env: Option<String>,
+
+    /// Path to an environment file, by default http-client.env.json
+    /// is looked up from the request file directory and its parents
+    #[arg(long, name = "ENV_FILE")]
+    env_file: Option<String>,
+
+    /// Path to an OpenAPI document to check
+    /// requests and responses against
+    #[arg(long, name = "SPEC")]
+    openapi: Option<String>,
+
+    /// Save the response body to a file instead of printing it
+    #[arg(long, name = "OUT")]
+    out: Option<String>,
+
+    /// Record executed requests and responses to a HAR file
+    #[arg(long, name = "HAR")]
+    har: Option<String>,
+
+    /// What to print of each response
+    #[arg(short, long, name = "MODE", value_enum, default_value_t = OutputMode::Full)]
+    output: OutputMode,
+
+    /// Filter JSON response bodies with a jq style expression,
+    /// e.g. '.data[] | select(.active) | .id'
+    #[arg(long, name = "FILTER")]
+    filter: Option<String>,
+
+    /// Print filtered strings without quotes
+    #[arg(short, long)]
+    raw: bool,
+
+    /// When to colour the output
+    #[arg(long, name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Timeout for each request, e.g. 5s or 500ms,
+    /// unless set with # @timeout
+    #[arg(long, name = "DURATION")]
+    timeout: Option<String>,
+
+    /// Timeout for connecting, unless set with # @connect-timeout
+    #[arg(long, name = "CONNECT_DURATION")]
+    connect_timeout: Option<String>,
+
+    /// Retry failed requests unless set with # @retry,
+    /// e.g. '3 backoff=exponential on=502,503,504,connect'
+    #[arg(long, name = "RETRY")]
+    retry: Option<String>,
+
+    /// Do not follow redirects, unless set with # @max-redirects
+    #[arg(long, conflicts_with = "MAX_REDIRECTS")]
+    no_follow: bool,
+
+    /// Redirects to follow, 10 by default, unless set with
+    /// # @max-redirects or # @no-redirect
+    #[arg(long, name = "MAX_REDIRECTS")]
+    max_redirects: Option<usize>,
+
+    /// Load cookies from a file and save them back after the run
+    #[arg(long, name = "COOKIE_JAR")]
+    cookie_jar: Option<String>,
+
+    /// Do not keep cookies between requests
+    #[arg(long, conflicts_with = "COOKIE_JAR")]
+    no_cookies: bool,
+
+    /// Render the requests and print them without sending
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Show the request as sent, redirects and the connection's HTTP version
+    #[arg(short, long)]
+    verbose: bool,
+
     /// Show debug information
     #[arg(short, long)]
     debug: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate HTTP request files from other formats
+    #[command(subcommand)]
+    Import(ImportCommand),
+
+    /// Convert HTTP request files to other formats
+    #[command(subcommand)]
+    Export(ExportCommand),
+}
+
 fn parse_param(s: &str) -> anyhow::Result<KeyValue> {
-    let parts: Vec<&str> = s.split('=').collect();
-    if parts.len() != 2 {
+    let Some((key, value)) = s.split_once('=') else {
         anyhow::bail!("Invalid key=value pair: {}", s);
-    }
-    Ok(KeyValue(parts[0].to_string(), parts[1].to_string()))
+    };
+    Ok(KeyValue(key.to_string(), value.to_string()))
 }
 
 fn main() -> anyhow::Result<()> {
-    let args: Args = Args::parse();
+    let mut args: Args = Args::parse();
 
     if args.debug {
         // Print backtrace when a panic occurs
         std::env::set_var("RUST_BACKTRACE", "1");
     }
 
+    match args.command.take() {
+        Some(Command::Import(command)) => return import(command),
+        Some(Command::Export(command)) => return export(command),
+        None => {}
+    }
+
+    let openapi = args.openapi.clone();
+    let spec = match &openapi {
+        Some(path) => Some(OpenApiSpec::load(Path::new(path))?),
+        None => None,
+    };
+
+    let har = args.har.clone();
+    let output = OutputOptions {
+        mode: args.output,
+        painter: args.color.painter(),
+        filter: args.filter.as_deref().map(Filter::parse).transpose()?,
+        raw: args.raw,
+    };
+    let mut recorder = HarRecorder::default();
+
+    let cookie_jar = args.cookie_jar.clone();
+    let cookies = match (&cookie_jar, args.no_cookies) {
+        (_, true) => None,
+        (Some(path), false) => Some(Arc::new(CookieJar::load(Path::new(path))?)),
+        (None, false) => Some(Arc::new(CookieJar::default())),
+    };
+
+    let out = args.out.clone();
+    // flags apply to requests that do not set their own
+    let defaults: Vec<(&str, String)> = [
+        ("timeout", args.timeout.clone()),
+        ("connect-timeout", args.connect_timeout.clone()),
+        ("retry", args.retry.clone()),
+        (
+            "max-redirects",
+            match args.no_follow {
+                true => Some("0".to_string()),
+                false => args.max_redirects.map(|max| max.to_string()),
+            },
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value?)))
+    .collect();
+    let verbose = args.verbose;
+    let dry_run = args.dry_run;
+
     let mut context = RequestContext::from_args(args)?;
-    let client = Client::new();
-    let request = parse_http_file(&mut context, &client)?;
-    let response = request.send()?;
+    if dry_run {
+        context.offline();
+    }
+    let mut requests = parse_http_file(&mut context)?;
+
+    if dry_run {
+        for (index, request) in requests.iter().enumerate() {
+            if index > 0 {
+                println!("\n###\n");
+            }
+            output_raw_request(request);
+        }
+        return Ok(());
+    }
+
+    // the command line takes precedence over a redirect in the request file
+    if let Some(out) = out {
+        if requests.len() > 1 {
+            anyhow::bail!(
+                "--out can only be used with a single request, use >> in the request file instead"
+            );
+        }
+        for request in requests.iter_mut() {
+            request.output = Some(OutputFile {
+                path: PathBuf::from(&out),
+                overwrite: true,
+            });
+        }
+    }
+
+    for request in requests.iter_mut() {
+        for (key, value) in &defaults {
+            request
+                .metadata
+                .entry(key.to_string())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    let mut failures: Vec<String> = Vec::new();
+    // requests with the same settings share a client and its connections
+    let trace = TraceLog::default();
+    let mut clients = Clients::new(trace.clone(), cookies.clone());
+
+    let mut run = || -> anyhow::Result<()> {
+        for (index, request) in requests.iter().enumerate() {
+            // status codes and JSON objects are printed without a separating blank line
+            if index > 0 && !matches!(output.mode, OutputMode::Status | OutputMode::Json) {
+                println!();
+            }
+
+            let jar = cookies
+                .as_ref()
+                .filter(|_| request.metadata("no-cookie-jar").is_none());
+            let client = clients.get(request, jar.is_some())?;
+            let response = HttpResponse::send(request, &client, &trace, jar, verbose)?;
+            if verbose {
+                output_connection(&response);
+            }
+
+            output_http(request, &response, &output)?;
+            recorder.record(request, &response);
+
+            if let Some(schema) = request.metadata("expect-schema") {
+                let violations = validate_json(&context.resolve_path(schema), &response.body)?;
+                if !violations.is_empty() {
+                    output_schema_violations(schema, &violations, output.mode);
+                    failures.push(format!("Response does not match schema \"{}\"", schema));
+                }
+            }
+
+            if let (Some(spec), Some(path)) = (&spec, &openapi) {
+                let violations = spec.check(request, &response)?;
+                if !violations.is_empty() {
+                    output_contract_violations(path, &violations, output.mode);
+                    failures.push(format!(
+                        "Request does not match OpenAPI contract \"{}\"",
+                        path
+                    ));
+                }
+            }
+        }
+        Ok(())
+    };
+    let result = run();
+
+    // what was received so far is kept when a request fails
+    if let (Some(path), Some(cookies)) = (&cookie_jar, &cookies) {
+        cookies.save(Path::new(path))?;
+    }
+
+    if let Some(path) = &har {
+        recorder.write(Path::new(path))?;
+    }
+
+    result?;
+
+    if !failures.is_empty() {
+        anyhow::bail!(failures.join("\n"));
+    }
 
-    output_http(response)
+    Ok(())
 }
@@ -0,0 +1,512 @@
+use anyhow::Context as ErrorContext;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use fake::{uuid::UUIDv4, Fake};
+use handlebars::{
+    Context as VariableContext, Handlebars, Helper, HelperResult, Output, RenderContext,
+    RenderErrorReason,
+};
+use reqwest::{blocking::Client, Url};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::TcpListener,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::context::{escape_backslashes, unmark};
+
+// Environment variable holding the OAuth2 configurations
+pub const OAUTH2_CONFIG: &str = "$oauth2";
+
+// Tokens are renewed this many seconds before they expire
+const EXPIRY_MARGIN: i64 = 30;
+
+// Lifetime in seconds of tokens returned without expires_in
+const DEFAULT_EXPIRES_IN: i64 = 60 * 60;
+
+const DEFAULT_REDIRECT_URI: &str = "http://127.0.0.1:8765/callback";
+
+// How long to wait for the browser to come back with the authorization code
+const AUTHORIZATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// Set when requests are only rendered, e.g. for --dry-run or export, so no
+// token is requested and no browser is opened
+pub const OFFLINE: &str = "request::offline";
+
+// Helper to render an access token, e.g. {{$oauth2 keycloak}} for the
+// configuration named keycloak, or {{$oauth2}} for a single configuration
+pub fn oauth2_helper(
+    h: &Helper,
+    hb: &Handlebars,
+    ctx: &VariableContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    // the name can be given as is or as a quoted string
+    let name = h.param(0).map(|param| match param.relative_path() {
+        Some(path) => path.to_string(),
+        None => param.value().as_str().unwrap_or_default().to_string(),
+    });
+    let token = access_token(hb, ctx, name.as_deref())
+        .map_err(|e| RenderErrorReason::Other(format!("{:#}", e)))?;
+    out.write(&token)?;
+    Ok(())
+}
+
+fn access_token(
+    hb: &Handlebars,
+    ctx: &VariableContext,
+    name: Option<&str>,
+) -> anyhow::Result<String> {
+    let config = config(ctx, name)?;
+    // cached tokens are not printed either
+    if ctx.data().get(OFFLINE).is_some() {
+        return Ok(match name {
+            Some(name) => format!("<$oauth2 {}>", name),
+            None => "<$oauth2>".to_string(),
+        });
+    }
+
+    // values can refer to other variables, e.g. "client_secret": "{{clientSecret}}"
+    let config: Map<String, Value> = config
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(template) => Value::String(unmark(
+                    &hb.render_template_with_context(&escape_backslashes(&template), ctx)
+                        .with_context(|| format!("Failed to render OAuth2 \"{}\"", key))?,
+                )),
+                value => value,
+            };
+            Ok((key, value))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let key = format!(
+        "{:x}",
+        Sha256::digest(Value::Object(config.clone()).to_string())
+    );
+    let mut cache = TokenCache::load();
+    let cached = cache.tokens.get(&key).cloned();
+
+    if let Some(token) = &cached {
+        let expires_at = token["expires_at"].as_i64().unwrap_or_default();
+        if let Some(access_token) = token["access_token"].as_str() {
+            if expires_at - EXPIRY_MARGIN > Utc::now().timestamp() {
+                return Ok(access_token.to_string());
+            }
+        }
+    }
+    // an expired token is refreshed when possible, an unusable refresh token starts over
+    let refreshed = cached
+        .as_ref()
+        .and_then(|token| token["refresh_token"].as_str())
+        .and_then(|refresh_token| {
+            request_token(
+                &config,
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token),
+                ],
+            )
+            .ok()
+        });
+    let mut token = match refreshed {
+        Some(token) => token,
+        None => grant(&config)?,
+    };
+
+    // refresh tokens are often only returned with the first token
+    if token.get("refresh_token").is_none() {
+        if let Some(refresh_token) = cached.as_ref().and_then(|token| token.get("refresh_token")) {
+            token["refresh_token"] = refresh_token.clone();
+        }
+    }
+    let expires_in = token["expires_in"].as_i64().unwrap_or(DEFAULT_EXPIRES_IN);
+    token["expires_at"] = json!(Utc::now().timestamp().saturating_add(expires_in));
+    let access_token = token["access_token"]
+        .as_str()
+        .context("OAuth2 token response has no access_token")?
+        .to_string();
+    cache.tokens.insert(key, token);
+    cache.save()?;
+    Ok(access_token)
+}
+
+// The named configuration from the environment file
+fn config(ctx: &VariableContext, name: Option<&str>) -> anyhow::Result<Map<String, Value>> {
+    let configs = match &ctx.data()[OAUTH2_CONFIG] {
+        // environment values are kept as strings
+        Value::String(configs) => serde_json::from_str(configs)
+            .with_context(|| format!("\"{}\" should be a JSON object", OAUTH2_CONFIG))?,
+        Value::Null => anyhow::bail!("No \"{}\" in the environment file", OAUTH2_CONFIG),
+        configs => configs.clone(),
+    };
+    let config = match name {
+        Some(name) => configs
+            .get(name)
+            .with_context(|| format!("No OAuth2 configuration \"{}\"", name))?,
+        None if configs.get("grant_type").is_some() => &configs,
+        None => anyhow::bail!("OAuth2 configuration name is missing, e.g. {{{{$oauth2 name}}}}"),
+    };
+    config
+        .as_object()
+        .cloned()
+        .context("OAuth2 configuration should be a JSON object")
+}
+
+fn grant(config: &Map<String, Value>) -> anyhow::Result<Value> {
+    let param = |name: &str| config.get(name).and_then(Value::as_str);
+    let required = |name: &str| {
+        param(name).with_context(|| format!("OAuth2 configuration has no \"{}\"", name))
+    };
+
+    match required("grant_type")? {
+        "client_credentials" => request_token(config, &[("grant_type", "client_credentials")]),
+        "password" => request_token(
+            config,
+            &[
+                ("grant_type", "password"),
+                ("username", required("username")?),
+                ("password", required("password")?),
+            ],
+        ),
+        "refresh_token" => request_token(
+            config,
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", required("refresh_token")?),
+            ],
+        ),
+        "authorization_code" => authorization_code(config),
+        grant_type => anyhow::bail!(
+            "Unsupported OAuth2 grant_type \"{}\", expected client_credentials, password, refresh_token or authorization_code",
+            grant_type
+        ),
+    }
+}
+
+// Authorization code grant with PKCE, the code is received on a local redirect listener
+fn authorization_code(config: &Map<String, Value>) -> anyhow::Result<Value> {
+    let param = |name: &str| config.get(name).and_then(Value::as_str);
+    let auth_url = param("auth_url").context("OAuth2 configuration has no \"auth_url\"")?;
+    let client_id = param("client_id").context("OAuth2 configuration has no \"client_id\"")?;
+    let redirect_uri = param("redirect_uri").unwrap_or(DEFAULT_REDIRECT_URI);
+    let redirect = Url::parse(redirect_uri)
+        .with_context(|| format!("Invalid OAuth2 redirect_uri \"{}\"", redirect_uri))?;
+
+    let verifier = format!("{}{}", random_hex(), random_hex());
+    let challenge = pkce_challenge(&verifier);
+    let state = random_hex();
+
+    let mut url = Url::parse(auth_url)
+        .with_context(|| format!("Invalid OAuth2 auth_url \"{}\"", auth_url))?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("state", &state)
+        .append_pair("code_challenge", &challenge)
+        .append_pair("code_challenge_method", "S256");
+    if let Some(scope) = param("scope") {
+        url.query_pairs_mut().append_pair("scope", scope);
+    }
+
+    let host = redirect.host_str().unwrap_or("127.0.0.1");
+    let port = redirect.port_or_known_default().unwrap_or(80);
+    let listener = TcpListener::bind((host, port)).with_context(|| {
+        format!(
+            "Failed to listen for the OAuth2 redirect on {}:{}",
+            host, port
+        )
+    })?;
+
+    eprintln!("Open this URL to authorize:\n{}", url);
+    open_browser(url.as_str());
+
+    let code = receive_code(&listener, redirect.path(), &state)?;
+    request_token(
+        config,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", &verifier),
+        ],
+    )
+}
+
+// Waits for the browser to be redirected back with the authorization code
+fn receive_code(listener: &TcpListener, path: &str, state: &str) -> anyhow::Result<String> {
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + AUTHORIZATION_TIMEOUT;
+    loop {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    anyhow::bail!(
+                        "OAuth2 authorization timed out after {} minutes waiting for the redirect",
+                        AUTHORIZATION_TIMEOUT.as_secs() / 60
+                    );
+                }
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // browsers open connections ahead that may never send a request
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut request_line = String::new();
+        if BufReader::new(&stream)
+            .read_line(&mut request_line)
+            .is_err()
+        {
+            continue;
+        }
+
+        // e.g. GET /callback?code=...&state=... HTTP/1.1
+        let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+        let Ok(url) = Url::parse(&format!("http://localhost{}", target)) else {
+            continue;
+        };
+        if url.path() != path {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")?;
+            continue;
+        }
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+        };
+
+        let (status, message) = match (query("code"), query("error")) {
+            (Some(_), _) if query("state").as_deref() != Some(state) => (
+                "400 Bad Request",
+                "Authorization failed, the state does not match",
+            ),
+            (Some(_), _) => ("200 OK", "Authorized, you can close this window"),
+            _ => ("400 Bad Request", "Authorization failed"),
+        };
+        let body = format!("<html><body><p>{}</p></body></html>", message);
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+
+        if let Some(error) = query("error") {
+            anyhow::bail!(
+                "OAuth2 authorization failed: {} {}",
+                error,
+                query("error_description").unwrap_or_default()
+            );
+        }
+        if query("state").as_deref() != Some(state) {
+            anyhow::bail!("OAuth2 authorization failed, the state does not match");
+        }
+        if let Some(code) = query("code") {
+            return Ok(code);
+        }
+    }
+}
+
+fn request_token(config: &Map<String, Value>, grant: &[(&str, &str)]) -> anyhow::Result<Value> {
+    let param = |name: &str| config.get(name).and_then(Value::as_str);
+    let token_url = param("token_url").context("OAuth2 configuration has no \"token_url\"")?;
+
+    let mut form: Vec<(String, String)> = grant
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    for name in ["scope", "audience", "resource"] {
+        if let Some(value) = param(name) {
+            form.push((name.to_string(), value.to_string()));
+        }
+    }
+    // additional token request parameters
+    if let Some(params) = config.get("params").and_then(Value::as_object) {
+        for (key, value) in params {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            form.push((key.clone(), value));
+        }
+    }
+
+    let mut builder = Client::new().post(token_url);
+    // client credentials are sent as basic auth unless "client_auth": "body"
+    match (param("client_id"), param("client_secret")) {
+        (Some(client_id), Some(secret)) if param("client_auth") != Some("body") => {
+            builder = builder.basic_auth(client_id, Some(secret));
+        }
+        (Some(client_id), secret) => {
+            form.push(("client_id".to_string(), client_id.to_string()));
+            if let Some(secret) = secret {
+                form.push(("client_secret".to_string(), secret.to_string()));
+            }
+        }
+        (None, _) => {}
+    }
+
+    let response = builder
+        .header("accept", "application/json")
+        .form(&form)
+        .send()
+        .with_context(|| format!("Failed to request an OAuth2 token from \"{}\"", token_url))?;
+    let status = response.status();
+    let body = response.text()?;
+    let token: Value = serde_json::from_str(&body).unwrap_or(Value::Null);
+    if !status.is_success() {
+        anyhow::bail!(
+            "OAuth2 token request failed with {}: {}",
+            status,
+            match (&token["error"], &token["error_description"]) {
+                (Value::String(error), Value::String(description)) =>
+                    format!("{} {}", error, description),
+                (Value::String(error), _) => error.clone(),
+                _ => body,
+            }
+        );
+    }
+    if !token.is_object() {
+        anyhow::bail!("OAuth2 token response should be a JSON object: {}", body);
+    }
+    Ok(token)
+}
+
+// The S256 code challenge for a PKCE code verifier
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier))
+}
+
+fn random_hex() -> String {
+    UUIDv4.fake::<String>().replace('-', "")
+}
+
+// Best effort, the URL is printed in case no browser opens
+fn open_browser(url: &str) {
+    let mut command = match env::consts::OS {
+        "macos" => Command::new("open"),
+        "windows" => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    let _ = command
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+// Tokens by configuration, kept on disk until they expire
+struct TokenCache {
+    path: Option<PathBuf>,
+    tokens: Map<String, Value>,
+}
+
+impl TokenCache {
+    fn load() -> Self {
+        let path = cache_dir().map(|dir| dir.join("krill").join("oauth2.json"));
+        let tokens = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        TokenCache { path, tokens }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory \"{}\"", parent.display()))?;
+        }
+        // tokens are credentials, only the owner can read them. The file is
+        // created that way and moved in place, so it is never readable by others
+        let temporary = path.with_extension("json.tmp");
+        let _ = fs::remove_file(&temporary);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&temporary)
+            .with_context(|| format!("Failed to create file \"{}\"", temporary.display()))?;
+        file.write_all(serde_json::to_string_pretty(&self.tokens)?.as_bytes())
+            .with_context(|| format!("Failed to write file \"{}\"", temporary.display()))?;
+        fs::rename(&temporary, path)
+            .with_context(|| format!("Failed to write file \"{}\"", path.display()))?;
+        Ok(())
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_s256() {
+        // base64url of the SHA-256 digest, without padding
+        assert_eq!(
+            pkce_challenge("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef"),
+            "qK5ubukpq-o6_PxSWMjM1vhSc-DUYm0mxyefMlD3fI4"
+        );
+        assert_eq!(
+            pkce_challenge("verifier"),
+            "iMnq5o6zALKXGivsnlom_0F5_WYda32GHkxlV7mq7hQ"
+        );
+    }
+
+    #[test]
+    fn config_selection() {
+        let named = json!({
+            "keycloak": { "grant_type": "client_credentials", "client_id": "krill" },
+            "github": { "grant_type": "authorization_code" },
+        });
+        let ctx = VariableContext::wraps(json!({ OAUTH2_CONFIG: named })).unwrap();
+        assert_eq!(
+            config(&ctx, Some("keycloak")).unwrap()["client_id"],
+            "krill"
+        );
+        assert!(config(&ctx, Some("gitlab")).is_err());
+        // several configurations need a name
+        assert!(config(&ctx, None).is_err());
+
+        // environment values are JSON strings, a single configuration needs no name
+        let single = json!({ "grant_type": "password", "username": "admin" }).to_string();
+        let ctx = VariableContext::wraps(json!({ OAUTH2_CONFIG: single })).unwrap();
+        assert_eq!(config(&ctx, None).unwrap()["username"], "admin");
+
+        let ctx = VariableContext::wraps(json!({ OAUTH2_CONFIG: "not json" })).unwrap();
+        assert!(config(&ctx, None).is_err());
+        let ctx = VariableContext::wraps(json!({})).unwrap();
+        assert!(config(&ctx, Some("keycloak")).is_err());
+    }
+}
@@ -0,0 +1,607 @@
+use anyhow::Context as ErrorContext;
+use jsonschema::Draft;
+use percent_encoding::percent_decode_str;
+use reqwest::header::{HeaderMap, CONTENT_TYPE, COOKIE};
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+use crate::{
+    parser::HttpRequest,
+    response::HttpResponse,
+    schema::{resolve_ref, validate_value},
+};
+
+// Header parameters that OpenAPI says are ignored
+const IGNORED_HEADERS: [&str; 3] = ["accept", "content-type", "authorization"];
+
+pub struct ContractViolation {
+    pub location: String,
+    pub message: String,
+}
+
+pub struct OpenApiSpec {
+    document: Value,
+    components: Value,
+    draft: Draft,
+}
+
+struct Operation<'spec> {
+    path_item: &'spec Value,
+    operation: &'spec Value,
+    path_params: Vec<(String, String)>,
+}
+
+impl OpenApiSpec {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to open OpenAPI document \"{}\"", path.display()))?;
+        let document = parse_document(&content).with_context(|| {
+            format!(
+                "OpenAPI document should be valid YAML or JSON \"{}\"",
+                path.display()
+            )
+        })?;
+        Self::new(document)
+    }
+
+    fn new(document: Value) -> anyhow::Result<Self> {
+        // OpenAPI 3.0 schemas are an extended subset of JSON Schema draft 4,
+        // 3.1 schemas are full JSON Schema 2020-12
+        let draft = match document["openapi"].as_str() {
+            Some(version) if version.starts_with("3.0") => Draft::Draft4,
+            Some(version) if version.starts_with("3.") => Draft::Draft202012,
+            _ => anyhow::bail!("Only OpenAPI 3.x documents are supported"),
+        };
+        let components = normalize_schema(document["components"].clone());
+        Ok(OpenApiSpec {
+            document,
+            components,
+            draft,
+        })
+    }
+
+    pub fn check(
+        &self,
+        request: &HttpRequest,
+        response: &HttpResponse,
+    ) -> anyhow::Result<Vec<ContractViolation>> {
+        let mut violations = Vec::new();
+
+        let method = request.method.as_str().to_lowercase();
+        let Some(operation) = self.find_operation(&method, request.url.path()) else {
+            violations.push(violation(
+                "request",
+                format!(
+                    "No operation matches {} {}",
+                    request.method,
+                    request.url.path()
+                ),
+            ));
+            return Ok(violations);
+        };
+
+        self.check_parameters(&operation, request, &mut violations)?;
+        self.check_request_body(&operation, request, &mut violations)?;
+        self.check_response(&operation, response, &mut violations)?;
+
+        Ok(violations)
+    }
+
+    pub fn document(&self) -> &Value {
+        &self.document
+    }
+
+    pub fn resolve<'spec>(&'spec self, value: &'spec Value) -> &'spec Value {
+        resolve_ref(&self.document, value)
+    }
+
+    fn base_paths(&self) -> Vec<String> {
+        let mut base_paths: Vec<String> = self.document["servers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|server| server["url"].as_str())
+            .map(|url| {
+                let path = match url.split_once("://") {
+                    Some((_, rest)) => rest.find('/').map_or("", |index| &rest[index..]),
+                    None => url,
+                };
+                path.trim_end_matches('/').to_string()
+            })
+            .collect();
+        if base_paths.is_empty() {
+            base_paths.push(String::new());
+        }
+        base_paths
+    }
+
+    fn find_operation(&self, method: &str, path: &str) -> Option<Operation<'_>> {
+        let paths = self.document["paths"].as_object()?;
+        let mut best: Option<(usize, Operation)> = None;
+
+        for base_path in self.base_paths() {
+            for (template, path_item) in paths {
+                let path_item = self.resolve(path_item);
+                let Some(operation) = path_item.get(method) else {
+                    continue;
+                };
+                let Some((literals, path_params)) =
+                    match_path(&format!("{}{}", base_path, template), path)
+                else {
+                    continue;
+                };
+                // concrete paths take precedence over templated ones
+                if best.as_ref().is_none_or(|(score, _)| literals > *score) {
+                    let operation = Operation {
+                        path_item,
+                        operation,
+                        path_params,
+                    };
+                    best = Some((literals, operation));
+                }
+            }
+        }
+
+        best.map(|(_, operation)| operation)
+    }
+
+    fn check_parameters(
+        &self,
+        operation: &Operation,
+        request: &HttpRequest,
+        violations: &mut Vec<ContractViolation>,
+    ) -> anyhow::Result<()> {
+        // operation level parameters override path level ones with the same name and location
+        let mut parameters: Vec<&Value> = Vec::new();
+        let declared = [operation.path_item, operation.operation]
+            .into_iter()
+            .filter_map(|item| item["parameters"].as_array())
+            .flatten()
+            .map(|parameter| self.resolve(parameter));
+        for parameter in declared {
+            parameters.retain(|existing| {
+                existing["name"] != parameter["name"] || existing["in"] != parameter["in"]
+            });
+            parameters.push(parameter);
+        }
+
+        for parameter in parameters {
+            let name = parameter["name"].as_str().unwrap_or_default();
+            let location = parameter["in"].as_str().unwrap_or_default();
+            // these headers are described by the media types and security schemes instead
+            if location == "header" && IGNORED_HEADERS.contains(&name.to_lowercase().as_str()) {
+                continue;
+            }
+            let required = parameter["required"]
+                .as_bool()
+                .unwrap_or(location == "path");
+
+            let values: Vec<String> = match location {
+                "path" => operation
+                    .path_params
+                    .iter()
+                    .filter(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+                    .collect(),
+                "query" => request
+                    .url
+                    .query_pairs()
+                    .filter(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+                    .collect(),
+                "header" => header_values(&request.headers, name),
+                "cookie" => header_values(&request.headers, COOKIE.as_str())
+                    .iter()
+                    .flat_map(|cookies| cookies.split(';'))
+                    .filter_map(|cookie| cookie.trim().split_once('='))
+                    .filter(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+                    .collect(),
+                _ => continue,
+            };
+
+            let location = format!("request.{}.{}", location, name);
+            if values.is_empty() {
+                if required {
+                    violations.push(violation(&location, "Required parameter is missing"));
+                }
+                continue;
+            }
+
+            if let Some(schema) = parameter.get("schema") {
+                let instance = coerce_parameter(&self.document, schema, &values);
+                self.validate(&location, schema, &instance, violations)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_request_body(
+        &self,
+        operation: &Operation,
+        request: &HttpRequest,
+        violations: &mut Vec<ContractViolation>,
+    ) -> anyhow::Result<()> {
+        let location = "request.body";
+        let Some(request_body) = operation.operation.get("requestBody") else {
+            if request.body.is_some() {
+                violations.push(violation(location, "Request body is not documented"));
+            }
+            return Ok(());
+        };
+        let request_body = self.resolve(request_body);
+
+        let Some(body) = &request.body else {
+            if request_body["required"].as_bool().unwrap_or(false) {
+                violations.push(violation(location, "Required request body is missing"));
+            }
+            return Ok(());
+        };
+
+        let content_type = header_values(&request.headers, CONTENT_TYPE.as_str());
+        self.check_content(
+            location,
+            &request_body["content"],
+            content_type.first().map(String::as_str),
+            &String::from_utf8_lossy(body),
+            violations,
+        )
+    }
+
+    fn check_response(
+        &self,
+        operation: &Operation,
+        response: &HttpResponse,
+        violations: &mut Vec<ContractViolation>,
+    ) -> anyhow::Result<()> {
+        let responses = &operation.operation["responses"];
+        let status = response.status.as_str();
+        let range = format!("{}XX", &status[..1]);
+
+        let documented = responses
+            .get(status)
+            .or_else(|| responses.get(&range))
+            .or_else(|| responses.get(range.to_lowercase()))
+            .or_else(|| responses.get("default"));
+        let Some(documented) = documented else {
+            violations.push(violation(
+                "response.status",
+                format!("Status {} is not documented", status),
+            ));
+            return Ok(());
+        };
+
+        if response.body.is_empty() {
+            return Ok(());
+        }
+
+        let content_type = header_values(&response.headers, CONTENT_TYPE.as_str());
+        self.check_content(
+            "response.body",
+            &self.resolve(documented)["content"],
+            content_type.first().map(String::as_str),
+            &response.body,
+            violations,
+        )
+    }
+
+    fn check_content(
+        &self,
+        location: &str,
+        content: &Value,
+        content_type: Option<&str>,
+        body: &str,
+        violations: &mut Vec<ContractViolation>,
+    ) -> anyhow::Result<()> {
+        let Some(content) = content.as_object() else {
+            return Ok(());
+        };
+        let Some(content_type) = content_type else {
+            violations.push(violation(location, "Content type is missing"));
+            return Ok(());
+        };
+
+        let essence = media_type_essence(content_type);
+        let Some((_, media_type)) = content
+            .iter()
+            .find(|(documented, _)| media_type_matches(documented, &essence))
+        else {
+            let documented: Vec<&str> = content.keys().map(String::as_str).collect();
+            violations.push(violation(
+                location,
+                format!(
+                    "Content type \"{}\" is not documented, expected one of {}",
+                    essence,
+                    documented.join(", ")
+                ),
+            ));
+            return Ok(());
+        };
+
+        let Some(schema) = media_type.get("schema") else {
+            return Ok(());
+        };
+        if !is_json_media_type(&essence) {
+            return Ok(());
+        }
+        match serde_json::from_str::<Value>(body) {
+            Ok(instance) => self.validate(location, schema, &instance, violations),
+            Err(_) => {
+                violations.push(violation(location, "Body is not valid JSON"));
+                Ok(())
+            }
+        }
+    }
+
+    fn validate(
+        &self,
+        location: &str,
+        schema: &Value,
+        instance: &Value,
+        violations: &mut Vec<ContractViolation>,
+    ) -> anyhow::Result<()> {
+        // local references such as "#/components/schemas/User" are resolved
+        // against the wrapper root, so the components are carried along
+        let schema = json!({
+            "allOf": [normalize_schema(schema.clone())],
+            "components": self.components,
+        });
+        let found = validate_value(&schema, self.draft, instance)
+            .with_context(|| format!("Invalid schema for {}", location))?;
+        violations.extend(found.into_iter().map(|found| ContractViolation {
+            location: match instance.is_object() || instance.is_array() {
+                true => format!("{}{}", location, found.pointer),
+                false => location.to_string(),
+            },
+            message: found.message,
+        }));
+        Ok(())
+    }
+}
+
+fn media_type_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+fn is_json_media_type(essence: &str) -> bool {
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+fn parse_document(content: &str) -> anyhow::Result<Value> {
+    let document: serde_yaml::Value = serde_yaml::from_str(content)?;
+    Ok(yaml_to_json(document))
+}
+
+// YAML allows non-string mapping keys, e.g. unquoted response codes
+fn yaml_to_json(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => serde_json::to_value(n).unwrap_or(Value::Null),
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(seq) => {
+            Value::Array(seq.into_iter().map(yaml_to_json).collect())
+        }
+        serde_yaml::Value::Mapping(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = match yaml_to_json(key) {
+                        Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    (key, yaml_to_json(value))
+                })
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_json(tagged.value),
+    }
+}
+
+// Rewrite OpenAPI 3.0 `nullable` into a JSON Schema type union
+fn normalize_schema(value: Value) -> Value {
+    match value {
+        Value::Object(mut map) => {
+            if map.remove("nullable") == Some(Value::Bool(true)) {
+                if let Some(Value::String(kind)) = map.get("type") {
+                    let kind = kind.clone();
+                    map.insert("type".to_string(), json!([kind, "null"]));
+                }
+                if let Some(Value::Array(values)) = map.get_mut("enum") {
+                    values.push(Value::Null);
+                }
+            }
+            Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, normalize_schema(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(normalize_schema).collect()),
+        other => other,
+    }
+}
+
+fn match_path(template: &str, path: &str) -> Option<(usize, Vec<(String, String)>)> {
+    let template: Vec<&str> = template.trim_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_matches('/').split('/').collect();
+    if template.len() != path.len() {
+        return None;
+    }
+
+    let mut literals = 0;
+    let mut params = Vec::new();
+    for (template, segment) in template.iter().zip(path) {
+        let segment = percent_decode_str(segment).decode_utf8_lossy();
+        match template.split_once('{') {
+            Some((prefix, rest)) => {
+                let (name, suffix) = rest.split_once('}')?;
+                let value = segment.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                if value.is_empty() {
+                    return None;
+                }
+                params.push((name.to_string(), value.to_string()));
+            }
+            None if *template == segment => literals += 1,
+            None => return None,
+        }
+    }
+    Some((literals, params))
+}
+
+fn media_type_matches(documented: &str, essence: &str) -> bool {
+    let documented = media_type_essence(documented);
+    documented == essence
+        || documented == "*/*"
+        || documented
+            .strip_suffix("/*")
+            .is_some_and(|kind| essence.starts_with(&format!("{}/", kind)))
+}
+
+fn header_values(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(String::from)
+        .collect()
+}
+
+// Parameters arrive as strings, convert them to the type the schema expects
+fn coerce_parameter(root: &Value, schema: &Value, values: &[String]) -> Value {
+    let schema = resolve_ref(root, schema);
+    match schema["type"].as_str() {
+        Some("array") => {
+            let items = resolve_ref(root, &schema["items"]);
+            let values: Vec<&str> = match values {
+                [single] => single.split(',').collect(),
+                _ => values.iter().map(String::as_str).collect(),
+            };
+            Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| coerce_scalar(items, value))
+                    .collect(),
+            )
+        }
+        _ => coerce_scalar(schema, &values[0]),
+    }
+}
+
+fn coerce_scalar(schema: &Value, value: &str) -> Value {
+    let coerced = match schema["type"].as_str() {
+        Some("integer") => value.parse::<i64>().ok().map(Value::from),
+        Some("number") => value.parse::<f64>().ok().map(Value::from),
+        Some("boolean") => value.parse::<bool>().ok().map(Value::from),
+        _ => None,
+    };
+    coerced.unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+fn violation(location: &str, message: impl Into<String>) -> ContractViolation {
+    ContractViolation {
+        location: location.to_string(),
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{header::HeaderName, Method, Url, Version};
+
+    const SPEC: &str = r#"
+openapi: 3.0.3
+paths:
+  /users:
+    get:
+      parameters:
+        - $ref: '#/components/parameters/Ids'
+        - name: Accept
+          in: header
+          required: true
+          schema:
+            type: string
+            enum: [application/xml]
+        - name: X-Version
+          in: header
+          schema:
+            type: integer
+      responses:
+        '200':
+          description: Users
+components:
+  parameters:
+    Ids:
+      name: ids
+      in: query
+      required: true
+      schema:
+        $ref: '#/components/schemas/Ids'
+  schemas:
+    Ids:
+      type: array
+      items:
+        $ref: '#/components/schemas/Id'
+    Id:
+      type: integer
+      minimum: 1
+"#;
+
+    fn check_parameters(url: &str, headers: &[(&'static str, &str)]) -> Vec<String> {
+        let spec = OpenApiSpec::new(parse_document(SPEC).unwrap()).unwrap();
+        let request = HttpRequest {
+            method: Method::GET,
+            url: Url::parse(url).unwrap(),
+            version: Version::HTTP_11,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (HeaderName::from_static(name), value.parse().unwrap()))
+                .collect(),
+            body: None,
+            metadata: Default::default(),
+            output: None,
+            digest: None,
+            aws: None,
+        };
+        let operation = spec.find_operation("get", request.url.path()).unwrap();
+        let mut violations = Vec::new();
+        spec.check_parameters(&operation, &request, &mut violations)
+            .unwrap();
+        violations
+            .into_iter()
+            .map(|violation| violation.location)
+            .collect()
+    }
+
+    #[test]
+    fn referenced_item_schemas() {
+        assert!(check_parameters("http://localhost/users?ids=1,2", &[]).is_empty());
+        assert_eq!(
+            check_parameters("http://localhost/users?ids=1&ids=0", &[]),
+            ["request.query.ids#/1"]
+        );
+        assert_eq!(
+            check_parameters("http://localhost/users", &[]),
+            ["request.query.ids"]
+        );
+    }
+
+    #[test]
+    fn ignored_headers() {
+        assert!(check_parameters(
+            "http://localhost/users?ids=1",
+            &[("accept", "application/json"), ("x-version", "2")]
+        )
+        .is_empty());
+        assert_eq!(
+            check_parameters("http://localhost/users?ids=1", &[("x-version", "v2")]),
+            ["request.header.X-Version"]
+        );
+    }
+}
@@ -1,10 +1,410 @@
-use reqwest::blocking::Response;
+use clap::ValueEnum;
+use reqwest::{
+    blocking::{Body, Request},
+    header::{HeaderName, ACCEPT, ACCEPT_ENCODING, LOCATION},
+};
+use serde_json::{json, Map, Value};
+use std::{
+    io::{self, IsTerminal},
+    time::Duration,
+};
 
-pub fn output_http(response: Response) -> anyhow::Result<()> {
-    println!("{:?} {}", response.version(), response.status());
-    for (key, value) in response.headers().iter() {
-        println!("{}: {}", key, value.to_str().unwrap());
+use crate::{
+    client::wire_headers,
+    filter::Filter,
+    openapi::ContractViolation,
+    parser::HttpRequest,
+    pretty::{pretty_body, Painter, DIM},
+    response::{millis, HttpResponse},
+    schema::SchemaViolation,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputMode {
+    /// Status line, headers and body
+    Full,
+    /// Body only
+    Body,
+    /// Status line and headers only
+    Headers,
+    /// Status code only
+    Status,
+    /// A JSON object with the status, headers, body, timings and sizes
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ColorChoice {
+    /// Colour when printing to a terminal and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn painter(self) -> Painter {
+        Painter::new(match self {
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        })
+    }
+}
+
+pub struct OutputOptions {
+    pub mode: OutputMode,
+    pub painter: Painter,
+    pub filter: Option<Filter>,
+    // print filtered strings without quotes
+    pub raw: bool,
+}
+
+pub fn output_http(
+    request: &HttpRequest,
+    response: &HttpResponse,
+    options: &OutputOptions,
+) -> anyhow::Result<()> {
+    output_response(request, response, options)?;
+    // saved bodies are reported on stderr to keep stdout for the response
+    if let Some(path) = &response.saved {
+        eprintln!(
+            "Saved {} to \"{}\"",
+            format_size(response.size),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn output_response(
+    request: &HttpRequest,
+    response: &HttpResponse,
+    options: &OutputOptions,
+) -> anyhow::Result<()> {
+    let painter = options.painter;
+    let mode = options.mode;
+
+    match mode {
+        OutputMode::Status => {
+            let code = response.status.as_u16();
+            println!("{}", painter.status(code, &code.to_string()));
+            return Ok(());
+        }
+        OutputMode::Json => {
+            let envelope = serde_json::to_string(&json_envelope(request, response, options)?)?;
+            println!(
+                "{}",
+                pretty_body(&envelope, Some("application/json"), painter)
+            );
+            return Ok(());
+        }
+        OutputMode::Body => {
+            if response.saved.is_none() {
+                println!("{}", body(response, options)?);
+            }
+            return Ok(());
+        }
+        OutputMode::Full | OutputMode::Headers => {}
+    }
+
+    let status_line = format!("{:?} {}", response.version, response.status);
+    println!("{}", painter.status(response.status.as_u16(), &status_line));
+    for (key, value) in response.headers.iter() {
+        println!(
+            "{}: {}",
+            painter.header_name(key.as_str()),
+            String::from_utf8_lossy(value.as_bytes())
+        );
+    }
+    if mode == OutputMode::Full {
+        if response.saved.is_none() {
+            println!("\n{}", body(response, options)?);
+        }
+        output_metrics(request, response, painter);
     }
-    println!("\n{}", response.text()?);
     Ok(())
 }
+
+fn output_metrics(request: &HttpRequest, response: &HttpResponse, painter: Painter) {
+    let timings = &response.timings;
+    let mut times: Vec<String> = Vec::new();
+    if let Some(redirect) = timings.redirect {
+        times.push(format!("redirects {}", format_duration(redirect)));
+    }
+    if let Some(dns) = timings.dns {
+        times.push(format!("dns {}", format_duration(dns)));
+    }
+    if let Some(connect) = timings.connect {
+        times.push(format!("connect+TLS {}", format_duration(connect)));
+    }
+    times.push(format!(
+        "first byte {}",
+        format_duration(timings.first_byte)
+    ));
+    times.push(format!("transfer {}", format_duration(timings.receive)));
+    times.push(format!("total {}", format_duration(timings.total())));
+
+    let (request_headers, request_body) = request_size(request, response);
+    let sizes = format!(
+        "request {} (headers {}, body {}), response {} (headers {}, body {})",
+        format_size(request_headers + request_body),
+        format_size(request_headers),
+        format_size(request_body),
+        format_size(response.headers_size + response.size),
+        format_size(response.headers_size),
+        format_size(response.size),
+    );
+
+    println!();
+    println!(
+        "{}",
+        painter.paint(DIM, &format!("Time: {}", times.join(", ")))
+    );
+    println!("{}", painter.paint(DIM, &format!("Size: {}", sizes)));
+}
+
+// Size of the request line and headers, and of the body, as sent apart from the client's defaults
+fn request_size(request: &HttpRequest, response: &HttpResponse) -> (u64, u64) {
+    let target = match request.url.query() {
+        Some(query) => format!("{}?{}", request.url.path(), query),
+        None => request.url.path().to_string(),
+    };
+    let request_line = format!("{} {} {:?}\r\n", request.method, target, request.version);
+    let body = request.body.as_ref().map(Vec::len);
+    let headers = wire_headers(&request.url, &response.request_headers, body)
+        .iter()
+        .fold(request_line.len() + 2, |size, (key, value)| {
+            size + key.as_str().len() + value.len() + 4
+        });
+    (headers as u64, body.unwrap_or_default() as u64)
+}
+
+fn body(response: &HttpResponse, options: &OutputOptions) -> anyhow::Result<String> {
+    let painter = options.painter;
+    let Some(filter) = &options.filter else {
+        let content_type = response
+            .headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok());
+        return Ok(pretty_body(&response.body, content_type, painter));
+    };
+
+    // each filter result is printed on its own
+    let results: Vec<String> = filter_body(response, filter)?
+        .iter()
+        .map(|value| match (value, options.raw) {
+            (Value::String(value), true) => value.clone(),
+            (value, _) => pretty_body(&value.to_string(), Some("application/json"), painter),
+        })
+        .collect();
+    Ok(results.join("\n"))
+}
+
+fn filter_body(response: &HttpResponse, filter: &Filter) -> anyhow::Result<Vec<Value>> {
+    if let Some(path) = &response.saved {
+        anyhow::bail!(
+            "Response body saved to \"{}\" cannot be filtered",
+            path.display()
+        );
+    }
+    let json: Value = serde_json::from_str(&response.body)
+        .map_err(|e| anyhow::anyhow!("Response body should be valid JSON to filter: {}", e))?;
+    filter.apply(&json)
+}
+
+fn json_envelope(
+    request: &HttpRequest,
+    response: &HttpResponse,
+    options: &OutputOptions,
+) -> anyhow::Result<Value> {
+    // repeated headers such as set-cookie are collected into an array
+    let mut headers: Map<String, Value> = Map::new();
+    for key in response.headers.keys() {
+        let values: Vec<Value> = response
+            .headers
+            .get_all(key)
+            .iter()
+            .map(|value| Value::String(String::from_utf8_lossy(value.as_bytes()).to_string()))
+            .collect();
+        let value = match values.len() {
+            1 => values[0].clone(),
+            _ => Value::Array(values),
+        };
+        headers.insert(key.to_string(), value);
+    }
+
+    let (request_headers, request_body) = request_size(request, response);
+
+    let body = match &options.filter {
+        // a single filter result is the body as is, several are collected into an array
+        Some(filter) => {
+            let mut results = filter_body(response, filter)?;
+            match results.len() {
+                1 => results.remove(0),
+                _ => Value::Array(results),
+            }
+        }
+        None if response.saved.is_some() => Value::Null,
+        None => serde_json::from_str::<Value>(&response.body)
+            .unwrap_or_else(|_| Value::String(response.body.clone())),
+    };
+
+    Ok(json!({
+        "status": response.status.as_u16(),
+        "version": format!("{:?}", response.version),
+        "headers": headers,
+        "body": body,
+        "timings": {
+            "redirect": response.timings.redirect.map(millis),
+            "dns": response.timings.dns.map(millis),
+            "connect": response.timings.connect.map(millis),
+            "first_byte": millis(response.timings.first_byte),
+            "transfer": millis(response.timings.receive),
+            "total": millis(response.timings.total()),
+        },
+        "size": {
+            "request": {
+                "headers": request_headers,
+                "body": request_body,
+            },
+            "response": {
+                "headers": response.headers_size,
+                "body": response.size,
+            },
+        },
+        "file": response.saved.as_ref().map(|path| path.display().to_string()),
+    }))
+}
+
+// Human readable duration, e.g. 12.3 ms or 1.52 s
+pub fn format_duration(duration: Duration) -> String {
+    match duration.as_secs_f64() {
+        seconds if seconds >= 1.0 => format!("{:.2} s", seconds),
+        seconds => format!("{:.1} ms", seconds * 1000.0),
+    }
+}
+
+// Human readable size, e.g. 1.5 MiB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// The rendered request in the request file format
+pub fn output_raw_request(request: &HttpRequest) {
+    println!("{} {} {:?}", request.method, request.url, request.version);
+    for (key, value) in request.headers.iter() {
+        println!("{}: {}", key, String::from_utf8_lossy(value.as_bytes()));
+    }
+    if let Some(digest) = &request.digest {
+        println!(
+            "Authorization: Digest {} {}",
+            digest.username, digest.password
+        );
+    }
+    if let Some(body) = &request.body {
+        println!("\n{}", String::from_utf8_lossy(body));
+    }
+}
+
+// The request as sent, on stderr like the rest of the verbose output
+pub fn output_request(request: &Request) {
+    eprintln!(
+        "> {} {} {:?}",
+        request.method(),
+        request.url(),
+        request.version()
+    );
+    let body = request.body().and_then(Body::as_bytes);
+    let headers = wire_headers(request.url(), request.headers(), body.map(<[u8]>::len));
+    for (key, value) in headers.iter() {
+        eprintln!("> {}: {}", key, String::from_utf8_lossy(value.as_bytes()));
+    }
+    // added further down the client, their values depend on how it was built
+    let defaults: Vec<&str> = [ACCEPT, ACCEPT_ENCODING]
+        .iter()
+        .filter(|name| !headers.contains_key(*name))
+        .map(HeaderName::as_str)
+        .collect();
+    if !defaults.is_empty() {
+        eprintln!(
+            "* Client defaults for {} are added when sent",
+            defaults.join(" and ")
+        );
+    }
+    if let Some(body) = body {
+        eprintln!(">");
+        for line in String::from_utf8_lossy(body).lines() {
+            eprintln!("> {}", line);
+        }
+    }
+}
+
+pub fn output_connection(response: &HttpResponse) {
+    for redirect in &response.redirects {
+        eprintln!(
+            "* {} from {}, following to {}",
+            redirect.status, redirect.from, redirect.to
+        );
+    }
+    eprintln!(
+        "* Response from {} over {:?}",
+        response.url, response.version
+    );
+    // no connect time when the last hop went over an open connection
+    if response.timings.connect.is_none() {
+        eprintln!("* Sent over an open connection");
+    }
+    if response.status.is_redirection() {
+        if let Some(location) = response.headers.get(LOCATION) {
+            eprintln!(
+                "* Not following the redirect to {}",
+                String::from_utf8_lossy(location.as_bytes())
+            );
+        }
+    }
+}
+
+pub fn output_schema_violations(schema: &str, violations: &[SchemaViolation], mode: OutputMode) {
+    let mut lines = vec![format!(
+        "\nSchema \"{}\": {} violation(s)",
+        schema,
+        violations.len()
+    )];
+    for violation in violations {
+        lines.push(format!("  {}: {}", violation.pointer, violation.message));
+    }
+    output_violations(&lines, mode);
+}
+
+pub fn output_contract_violations(spec: &str, violations: &[ContractViolation], mode: OutputMode) {
+    let mut lines = vec![format!(
+        "\nOpenAPI \"{}\": {} violation(s)",
+        spec,
+        violations.len()
+    )];
+    for violation in violations {
+        lines.push(format!("  {}: {}", violation.location, violation.message));
+    }
+    output_violations(&lines, mode);
+}
+
+// Violations follow the full output, otherwise they go to stderr to keep
+// stdout to what was asked for, e.g. one JSON object per response
+fn output_violations(lines: &[String], mode: OutputMode) {
+    match mode {
+        OutputMode::Full => println!("{}", lines.join("\n")),
+        _ => eprintln!("{}", lines.join("\n")),
+    }
+}
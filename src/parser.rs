@@ -1,42 +1,118 @@
 use anyhow::{anyhow, Context as ErrorContext, Ok};
 use reqwest::{
     blocking::{Client, RequestBuilder},
-    header::{HeaderMap, HeaderName, HeaderValue},
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
     Method, Url, Version,
 };
 use std::{
-    fs::File,
+    collections::BTreeMap,
+    fs::{self, File},
     io::{self, BufRead},
+    path::PathBuf,
     str::FromStr,
 };
 
-use crate::context::RequestContext;
+use crate::{
+    auth::{basic_authorization, DigestAuth},
+    aws::AwsAuth,
+    context::RequestContext,
+};
+
+// The rendered request body, available to header templates
+const BODY_VARIABLE: &str = "body";
 
 #[derive(PartialEq, Eq)]
 enum ParserState {
     Base,
     Header,
     Body,
+    // after a response redirect, e.g. >> ./report.pdf
+    End,
+}
+
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: Url,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+    pub metadata: BTreeMap<String, String>,
+    pub output: Option<OutputFile>,
+    // answers a digest challenge instead of sending an authorization header
+    pub digest: Option<DigestAuth>,
+    // signs each attempt when it is sent
+    pub aws: Option<AwsAuth>,
+}
+
+// File to save the response body to
+pub struct OutputFile {
+    pub path: PathBuf,
+    // replace an existing file instead of saving under a new name
+    pub overwrite: bool,
+}
+
+impl HttpRequest {
+    pub fn builder(&self, client: &Client) -> RequestBuilder {
+        let mut builder = client
+            .request(self.method.clone(), self.url.clone())
+            .version(self.version)
+            .headers(self.headers.clone());
+
+        if let Some(body) = &self.body {
+            builder = builder.body(body.clone());
+        }
+
+        builder
+    }
+
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
 }
 
-pub fn parse_http_file(
+pub fn parse_http_file(context: &mut RequestContext) -> anyhow::Result<Vec<HttpRequest>> {
+    let mut requests = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in read_lines(context.file())?.map_while(Result::ok) {
+        // requests are separated by lines starting with ###
+        if line.trim_start().starts_with("###") {
+            requests.extend(parse_request(context, &lines)?);
+            lines.clear();
+            continue;
+        }
+        lines.push(line);
+    }
+    requests.extend(parse_request(context, &lines)?);
+
+    Ok(requests)
+}
+
+fn parse_request(
     context: &mut RequestContext,
-    client: &Client,
-) -> anyhow::Result<RequestBuilder> {
+    lines: &[String],
+) -> anyhow::Result<Option<HttpRequest>> {
     let mut method: Option<Method> = None;
     let mut url: Option<Url> = None;
     let mut version: Option<Version> = None;
     let mut headers = HeaderMap::new();
+    let mut header_lines: Vec<String> = Vec::new();
     let mut body: Vec<String> = Vec::new();
+    let mut metadata: BTreeMap<String, String> = BTreeMap::new();
+    let mut output: Option<OutputFile> = None;
+    let mut digest: Option<DigestAuth> = None;
+    let mut aws: Option<AwsAuth> = None;
 
     let mut state = ParserState::Base;
 
-    for line in read_lines(context.file())?.map_while(Result::ok) {
+    for line in lines {
         let trimmed = line.trim().to_string();
-        let mut chunks = trimmed.split_ascii_whitespace();
 
-        // skip comment lines
-        if trimmed.starts_with("#") {
+        // parse request metadata and skip comment lines
+        if let Some(comment) = trimmed.strip_prefix("#") {
+            if state == ParserState::Base {
+                parse_metadata(&mut metadata, comment);
+            }
             continue;
         }
 
@@ -51,40 +127,167 @@ pub fn parse_http_file(
                     parse_variable(context, &trimmed)?;
                     continue;
                 }
-                // parse request line
+                // parse request line, templates in the url may contain spaces
+                let mut chunks = split_request_line(&trimmed).into_iter();
                 method = Some(parse_method(chunks.next())?);
                 url = Some(parse_url(context, chunks.next())?);
                 version = Some(parse_version(chunks.next())?);
                 state = ParserState::Header;
             }
+            ParserState::Header | ParserState::Body if trimmed.starts_with(">>") => {
+                output = Some(parse_output(context, &trimmed)?);
+                state = ParserState::End;
+            }
             ParserState::Header => {
                 if trimmed.is_empty() {
                     state = ParserState::Body;
                     continue;
                 }
-                let (key, val) = parse_header(context, chunks.next(), chunks.next())?;
-                headers.insert(key, val);
+                header_lines.push(trimmed);
             }
             ParserState::Body => {
-                if trimmed.is_empty() {
-                    break;
-                }
                 body.push(line.clone());
             }
+            ParserState::End => {}
         }
     }
 
-    let mut builder = client
-        .request(method.unwrap(), url.unwrap())
-        .version(version.unwrap())
-        .headers(headers);
+    // blocks with only variables or comments do not define a request
+    let Some(method) = method else {
+        return Ok(None);
+    };
 
-    if !body.is_empty() {
-        let rendered_body = context.render(&body.join("\n"))?;
-        builder = builder.body(rendered_body);
+    // trailing empty lines before the next request are not part of the body
+    while body.last().is_some_and(|line| line.trim().is_empty()) {
+        body.pop();
+    }
+
+    let body = match body.is_empty() {
+        true => None,
+        false => Some(render_body(context, &body, &header_lines)?),
+    };
+
+    // headers are rendered after the body so they can refer to it, e.g. to sign it
+    let previous = context.remove_variable(BODY_VARIABLE);
+    context.variable(
+        BODY_VARIABLE,
+        &String::from_utf8_lossy(body.as_deref().unwrap_or_default()),
+    );
+    let parsed: anyhow::Result<Vec<(HeaderName, HeaderValue)>> = header_lines
+        .iter()
+        .map(|line| parse_header(context, line))
+        .collect();
+    context.remove_variable(BODY_VARIABLE);
+    if let Some(previous) = previous {
+        context.variable(BODY_VARIABLE, &previous);
+    }
+
+    for (key, val) in parsed? {
+        if key == AUTHORIZATION {
+            if let Some(auth) = val.to_str().ok().and_then(DigestAuth::parse) {
+                digest = Some(auth);
+                continue;
+            }
+            if let Some(auth) = val.to_str().ok().and_then(AwsAuth::parse) {
+                aws = Some(auth?);
+                continue;
+            }
+        }
+        headers.insert(key, val);
     }
 
-    Ok(builder)
+    if let Some(spec) = metadata.get("auth") {
+        if let Some(auth) =
+            AwsAuth::from_metadata(spec).with_context(|| format!("Invalid @auth \"{}\"", spec))?
+        {
+            aws = Some(auth);
+        }
+    }
+
+    Ok(Some(HttpRequest {
+        method,
+        url: url.unwrap(),
+        version: version.unwrap(),
+        headers,
+        body,
+        metadata,
+        output,
+        digest,
+        aws,
+    }))
+}
+
+// Body lines like "< ./photo.png" are replaced by the file as it is, and lines
+// like "<@ ./body.json" by the rendered file, as in REST Client. Values in the
+// body are escaped as JSON strings for JSON content types, and lines of
+// multipart bodies end with CRLF as RFC 7578 requires
+fn render_body(
+    context: &RequestContext,
+    lines: &[String],
+    header_lines: &[String],
+) -> anyhow::Result<Vec<u8>> {
+    let content_type = content_type(context, header_lines);
+    let json = content_type.contains("json");
+    let newline = match content_type.starts_with("multipart/") {
+        true => "\r\n",
+        false => "\n",
+    };
+
+    let mut parts: Vec<Vec<u8>> = Vec::new();
+    let mut text: Vec<&str> = Vec::new();
+    for line in lines {
+        let Some((path, templated)) = file_reference(line) else {
+            text.push(line);
+            continue;
+        };
+        if !text.is_empty() {
+            parts.push(context.render_body(&text.join(newline), json)?.into_bytes());
+            text.clear();
+        }
+        let path = context.resolve_path(path);
+        let content = fs::read(&path)
+            .with_context(|| format!("Failed to read file \"{}\"", path.display()))?;
+        parts.push(match templated {
+            true => {
+                let template = String::from_utf8(content)
+                    .with_context(|| format!("File \"{}\" is not UTF-8", path.display()))?;
+                context.render_body(&template, json)?.into_bytes()
+            }
+            false => content,
+        });
+    }
+    if !text.is_empty() {
+        parts.push(context.render_body(&text.join(newline), json)?.into_bytes());
+    }
+    Ok(parts.join(newline.as_bytes()))
+}
+
+// "< path" for the file as it is, "<@ path" for the rendered file
+fn file_reference(line: &str) -> Option<(&str, bool)> {
+    let rest = line.strip_prefix('<')?;
+    let (rest, templated) = match rest.strip_prefix('@') {
+        Some(rest) => (rest, true),
+        None => (rest, false),
+    };
+    let path = rest.trim();
+    match rest.starts_with(char::is_whitespace) && !path.is_empty() {
+        true => Some((path, templated)),
+        false => None,
+    }
+}
+
+fn content_type(context: &RequestContext, header_lines: &[String]) -> String {
+    header_lines
+        .iter()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':').unwrap_or((line, ""));
+            match key.trim().eq_ignore_ascii_case(CONTENT_TYPE.as_str()) {
+                true => context.render(value.trim()).ok(),
+                false => None,
+            }
+        })
+        .unwrap_or_default()
+        .to_ascii_lowercase()
 }
 
 fn read_lines(path: &str) -> anyhow::Result<io::Lines<io::BufReader<File>>> {
@@ -94,17 +297,65 @@ fn read_lines(path: &str) -> anyhow::Result<io::Lines<io::BufReader<File>>> {
 
 fn parse_variable(context: &mut RequestContext, line: &String) -> anyhow::Result<()> {
     let rendered = &context.render(line)?;
-    let parts: Vec<&str> = rendered.split('=').map(|p| p.trim()).collect();
-    if parts.len() != 2 {
+    // values may contain "=", e.g. @token = abc==
+    let Some((key, value)) = rendered.split_once('=') else {
         anyhow::bail!("Invalid variable definition: {}", line);
-    }
-    let key = parts[0].trim_start_matches('@');
+    };
+    let key = key.trim().trim_start_matches('@');
     if !context.contains(key) {
-        context.variable(key, parts[1]);
+        context.variable(key, value.trim());
     }
     Ok(())
 }
 
+fn parse_metadata(metadata: &mut BTreeMap<String, String>, comment: &str) {
+    if let Some(meta) = comment.trim().strip_prefix("@") {
+        let (key, value) = meta.split_once(char::is_whitespace).unwrap_or((meta, ""));
+        metadata.insert(key.to_string(), value.trim().to_string());
+    }
+}
+
+fn parse_output(context: &RequestContext, line: &str) -> anyhow::Result<OutputFile> {
+    // >>! replaces an existing file
+    let (overwrite, path) = match line.strip_prefix(">>!") {
+        Some(path) => (true, path),
+        None => (false, line.trim_start_matches(">>")),
+    };
+    let path = context.render(path.trim())?;
+    if path.is_empty() {
+        anyhow::bail!("Response redirect should have a file path: {}", line);
+    }
+    Ok(OutputFile {
+        path: context.resolve_path(&path),
+        overwrite,
+    })
+}
+
+// Split on whitespace outside of {{ }} expressions
+fn split_request_line(line: &str) -> Vec<&str> {
+    let bytes = line.as_bytes();
+    let mut chunks = Vec::new();
+    let mut start = None;
+    let mut depth = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        if bytes[index..].starts_with(b"{{") {
+            depth += 1;
+        } else if depth > 0 && bytes[..=index].ends_with(b"}}") {
+            depth -= 1;
+        }
+        match (byte.is_ascii_whitespace() && depth == 0, start) {
+            (true, Some(from)) => {
+                chunks.push(&line[from..index]);
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    chunks.extend(start.map(|from| &line[from..]));
+    chunks
+}
+
 fn parse_method(value: Option<&str>) -> anyhow::Result<Method> {
     let str_value = value.unwrap_or_default();
     let method = Method::from_str(str_value)
@@ -114,7 +365,7 @@ fn parse_method(value: Option<&str>) -> anyhow::Result<Method> {
 
 fn parse_url(contex: &RequestContext, value: Option<&str>) -> anyhow::Result<Url> {
     let str_value = value.unwrap_or_default();
-    let rendered = contex.render(str_value)?;
+    let rendered = contex.render_url(str_value)?;
     let url = Url::parse(&rendered)
         .with_context(|| format!("Url should be valid, got \"{}\"", str_value))?;
     Ok(url)
@@ -131,15 +382,13 @@ fn parse_version(value: Option<&str>) -> anyhow::Result<Version> {
     })
 }
 
-fn parse_header(
-    context: &RequestContext,
-    key: Option<&str>,
-    value: Option<&str>,
-) -> anyhow::Result<(HeaderName, HeaderValue)> {
-    let str_key = key.unwrap_or_default();
-    let str_val = value.unwrap_or_default();
-    let key = str_key.strip_suffix(":").unwrap_or_default();
-    let rendered_val = context.render(str_val)?;
+fn parse_header(context: &RequestContext, line: &str) -> anyhow::Result<(HeaderName, HeaderValue)> {
+    let (key, str_val) = line.split_once(':').unwrap_or((line, ""));
+    let key = key.trim();
+    let mut rendered_val = context.render(str_val.trim())?;
+    if key.eq_ignore_ascii_case("authorization") {
+        rendered_val = basic_authorization(&rendered_val);
+    }
     Ok((
         HeaderName::from_str(key)
             .with_context(|| format!("Invalid HTTP header key \"{}\"", key))?,
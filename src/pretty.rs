@@ -0,0 +1,212 @@
+use percent_encoding::percent_decode_str;
+use serde_json::Value;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+pub const DIM: &str = "\x1b[90m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+
+// Elements without a closing tag in HTML
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+#[derive(Clone, Copy)]
+pub struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    pub fn new(color: bool) -> Self {
+        Painter { color }
+    }
+
+    pub fn paint(&self, style: &str, text: &str) -> String {
+        match self.color && !text.is_empty() {
+            true => format!("{}{}{}", style, text, RESET),
+            false => text.to_string(),
+        }
+    }
+
+    pub fn status(&self, code: u16, text: &str) -> String {
+        let style = match code {
+            200..=299 => GREEN,
+            300..=399 => CYAN,
+            400..=499 => YELLOW,
+            500..=599 => RED,
+            _ => "",
+        };
+        self.paint(&format!("{}{}", BOLD, style), text)
+    }
+
+    pub fn header_name(&self, name: &str) -> String {
+        self.paint(CYAN, name)
+    }
+}
+
+// Pretty print a body based on its content type, falling back to the body as is
+pub fn pretty_body(body: &str, content_type: Option<&str>, painter: Painter) -> String {
+    let content_type = content_type.unwrap_or_default().to_lowercase();
+    let trimmed = body.trim_start();
+
+    if content_type.contains("json")
+        || (content_type.is_empty() && (trimmed.starts_with('{') || trimmed.starts_with('[')))
+    {
+        if let Ok(json) = serde_json::from_str::<Value>(body) {
+            let mut out = String::new();
+            write_json(&mut out, &json, 0, painter);
+            return out;
+        }
+    }
+    if content_type.contains("xml") || content_type.contains("html") {
+        return pretty_markup(body, content_type.contains("html"), painter);
+    }
+    if content_type.contains("x-www-form-urlencoded") {
+        return pretty_form(body, painter);
+    }
+    body.to_string()
+}
+
+fn write_json(out: &mut String, value: &Value, depth: usize, painter: Painter) {
+    let indent = "  ".repeat(depth + 1);
+    let closing = "  ".repeat(depth);
+    match value {
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (index, (key, value)) in map.iter().enumerate() {
+                out.push_str(&indent);
+                out.push_str(&painter.paint(BLUE, &Value::String(key.clone()).to_string()));
+                out.push_str(": ");
+                write_json(out, value, depth + 1, painter);
+                if index + 1 < map.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            out.push_str(&closing);
+            out.push('}');
+        }
+        Value::Array(values) if values.is_empty() => out.push_str("[]"),
+        Value::Array(values) => {
+            out.push_str("[\n");
+            for (index, value) in values.iter().enumerate() {
+                out.push_str(&indent);
+                write_json(out, value, depth + 1, painter);
+                if index + 1 < values.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            out.push_str(&closing);
+            out.push(']');
+        }
+        Value::String(_) => out.push_str(&painter.paint(GREEN, &value.to_string())),
+        Value::Number(_) => out.push_str(&painter.paint(YELLOW, &value.to_string())),
+        Value::Bool(_) | Value::Null => out.push_str(&painter.paint(MAGENTA, &value.to_string())),
+    }
+}
+
+// Indent tags by nesting, keeping text and the content of script and style elements as is
+fn pretty_markup(body: &str, html: bool, painter: Painter) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut depth: usize = 0;
+    let mut rest = body;
+
+    while !rest.is_empty() {
+        let (token, remaining) = match rest.starts_with('<') {
+            true => {
+                let end = if rest.starts_with("<!--") {
+                    rest.find("-->").map(|end| end + 3)
+                } else if rest.starts_with("<![CDATA[") {
+                    rest.find("]]>").map(|end| end + 3)
+                } else {
+                    rest.find('>').map(|end| end + 1)
+                };
+                rest.split_at(end.unwrap_or(rest.len()))
+            }
+            false => rest.split_at(rest.find('<').unwrap_or(rest.len())),
+        };
+        rest = remaining;
+
+        let text = token.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if !token.starts_with('<') {
+            lines.push(format!("{}{}", "  ".repeat(depth), text));
+            continue;
+        }
+        if text.starts_with("<!--") {
+            lines.push(format!(
+                "{}{}",
+                "  ".repeat(depth),
+                painter.paint(DIM, text)
+            ));
+            continue;
+        }
+
+        let name: String = text
+            .trim_start_matches(['<', '/'])
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '>' && *c != '/')
+            .collect::<String>()
+            .to_lowercase();
+        let closing = text.starts_with("</");
+        let void = html && VOID_ELEMENTS.contains(&name.as_str());
+        let opening = !(closing
+            || void
+            || text.starts_with("<?")
+            || text.starts_with("<!")
+            || text.ends_with("/>"));
+
+        if closing {
+            depth = depth.saturating_sub(1);
+        }
+        lines.push(format!(
+            "{}{}",
+            "  ".repeat(depth),
+            painter.paint(BLUE, text)
+        ));
+        if opening {
+            depth += 1;
+            // raw text elements are printed as is up to their closing tag
+            if html && (name == "script" || name == "style") {
+                let end = rest
+                    .to_ascii_lowercase()
+                    .find(&format!("</{}", name))
+                    .unwrap_or(rest.len());
+                let (content, remaining) = rest.split_at(end);
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    lines.push(format!("{}{}", "  ".repeat(depth), line.trim()));
+                }
+                rest = remaining;
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+fn pretty_form(body: &str, painter: Painter) -> String {
+    let decode = |value: &str| {
+        percent_decode_str(&value.replace('+', " "))
+            .decode_utf8_lossy()
+            .to_string()
+    };
+    body.trim()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            format!("{}={}", painter.paint(BLUE, &decode(key)), decode(value))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
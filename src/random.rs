@@ -0,0 +1,181 @@
+use fake::Fake;
+use handlebars::{
+    Context as VariableContext, Handlebars, Helper, HelperResult, Output, RenderContext,
+    RenderError, RenderErrorReason,
+};
+use regex_syntax::hir::{Class, ClassUnicode, ClassUnicodeRange, Hir, HirKind};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::{schema::random_json, transform::param};
+
+// Repetitions without an upper bound, e.g. a+ or \d*, repeat up to this many more times
+const MAX_REPEAT: u32 = 8;
+
+// {{$randomRegex '[A-Z]{3}-\\d{4}'}}
+pub fn random_regex(h: &Helper) -> Result<Value, RenderError> {
+    let pattern = param(h, 0, "pattern")?;
+    let value = regex_sample(&pattern).map_err(|e| {
+        RenderErrorReason::Other(format!(
+            "$randomRegex pattern \"{}\" is invalid: {:#}",
+            pattern, e
+        ))
+    })?;
+    Ok(value.into())
+}
+
+// Helper to render a random document valid against a JSON schema,
+// e.g. {{$randomFromSchema './user.schema.json'}}
+pub fn random_from_schema_helper(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &VariableContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let param = h.param(0).ok_or_else(|| {
+        RenderErrorReason::Other("$randomFromSchema is missing the schema path".to_string())
+    })?;
+    // the path can be given as is or as a quoted string
+    let path = match param.relative_path() {
+        Some(path) if param.is_value_missing() => path.to_string(),
+        _ => param.value().as_str().unwrap_or_default().to_string(),
+    };
+    let path = match Path::new(&path).is_absolute() {
+        true => Path::new(&path).to_path_buf(),
+        false => {
+            let work_dir = ctx.data()["request::dir"].as_str().unwrap_or_default();
+            Path::new(work_dir).join(&path)
+        }
+    };
+    let document = random_json(&path).map_err(|e| RenderErrorReason::Other(format!("{:#}", e)))?;
+    out.write(&document)?;
+    Ok(())
+}
+
+// Random string matching a regular expression, anchors are left out
+pub fn regex_sample(pattern: &str) -> anyhow::Result<String> {
+    let hir = regex_syntax::Parser::new().parse(pattern)?;
+    let mut out = String::new();
+    write_regex_sample(&hir, &mut out);
+    Ok(out)
+}
+
+fn write_regex_sample(hir: &Hir, out: &mut String) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => {}
+        HirKind::Literal(literal) => out.push_str(&String::from_utf8_lossy(&literal.0)),
+        HirKind::Class(Class::Unicode(class)) => out.extend(random_char(class)),
+        HirKind::Class(Class::Bytes(class)) => {
+            let ranges: Vec<ClassUnicodeRange> = class
+                .ranges()
+                .iter()
+                .map(|range| ClassUnicodeRange::new(range.start().into(), range.end().into()))
+                .collect();
+            out.extend(random_char(&ClassUnicode::new(ranges)));
+        }
+        HirKind::Repetition(repetition) => {
+            let max = repetition.max.unwrap_or(repetition.min + MAX_REPEAT);
+            for _ in 0..(repetition.min..=max).fake::<u32>() {
+                write_regex_sample(&repetition.sub, out);
+            }
+        }
+        HirKind::Capture(capture) => write_regex_sample(&capture.sub, out),
+        HirKind::Concat(hirs) => hirs.iter().for_each(|hir| write_regex_sample(hir, out)),
+        HirKind::Alternation(hirs) => {
+            write_regex_sample(&hirs[(0..hirs.len()).fake::<usize>()], out)
+        }
+    }
+}
+
+// Printable ASCII characters are preferred, e.g. for . or \w
+fn random_char(class: &ClassUnicode) -> Option<char> {
+    let mut printable = ClassUnicode::new([ClassUnicodeRange::new(' ', '~')]);
+    printable.intersect(class);
+    let class = match printable.ranges().is_empty() {
+        true => class,
+        false => &printable,
+    };
+    let size = |range: &ClassUnicodeRange| range.end() as u32 - range.start() as u32 + 1;
+    let total: u32 = class.ranges().iter().map(size).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut index = (0..total).fake::<u32>();
+    for range in class.ranges() {
+        if index < size(range) {
+            return char::from_u32(range.start() as u32 + index);
+        }
+        index -= size(range);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::collections::BTreeSet;
+
+    // Samples of a pattern, each checked against the pattern itself
+    fn samples(pattern: &str) -> Vec<String> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern)).unwrap();
+        (0..200)
+            .map(|_| {
+                let sample = regex_sample(pattern).unwrap();
+                assert!(regex.is_match(&sample), "{:?} for {}", sample, pattern);
+                sample
+            })
+            .collect()
+    }
+
+    #[test]
+    fn repetition_bounds() {
+        assert!(samples("[A-Z]{3}-\\d{4}")
+            .iter()
+            .all(|sample| sample.len() == 8));
+        let lengths: BTreeSet<usize> = samples("a{2,4}").iter().map(String::len).collect();
+        assert_eq!(lengths, BTreeSet::from([2, 3, 4]));
+        let lengths: BTreeSet<usize> = samples("b+").iter().map(String::len).collect();
+        assert_eq!(lengths.first(), Some(&1));
+        assert!(lengths.last() <= Some(&(1 + MAX_REPEAT as usize)));
+        assert!(samples("c*").iter().any(String::is_empty));
+        assert!(samples("x?y").iter().any(|sample| sample == "y"));
+    }
+
+    #[test]
+    fn classes() {
+        let chars: BTreeSet<char> = samples("[a-c]{20}").concat().chars().collect();
+        assert_eq!(chars, BTreeSet::from(['a', 'b', 'c']));
+        assert!(samples("[^0-9]")
+            .concat()
+            .chars()
+            .all(|c| !c.is_ascii_digit()));
+        assert!(samples("\\w\\s.")
+            .iter()
+            .all(|sample| sample.chars().all(|c| (' '..='~').contains(&c))));
+        samples("(?i)ab[x-z]");
+    }
+
+    #[test]
+    fn alternation() {
+        let values: BTreeSet<String> = samples("(foo|ba[rz])").into_iter().collect();
+        assert_eq!(
+            values,
+            BTreeSet::from(["foo".to_string(), "bar".to_string(), "baz".to_string()])
+        );
+        assert_eq!(
+            samples("^id-(?:1|22)$")
+                .iter()
+                .collect::<BTreeSet<_>>()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(regex_sample("[a-").is_err());
+        assert!(regex_sample("a{2,1}").is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use anyhow::Context as ErrorContext;
+use chrono::{DateTime, Utc};
+use reqwest::{
+    blocking::{Client, Request, Response},
+    cookie::CookieStore,
+    header::{HeaderMap, AUTHORIZATION, COOKIE},
+    StatusCode, Url, Version,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client::{Redirect, RetryPolicy, Trace, TraceLog},
+    cookies::CookieJar,
+    output::{format_duration, format_size, output_request},
+    parser::{HttpRequest, OutputFile},
+};
+
+// Downloads smaller than this finish without a progress indicator
+const PROGRESS_THRESHOLD: u64 = 1024 * 1024;
+
+pub struct HttpResponse {
+    // final URL after redirects
+    pub url: Url,
+    pub version: Version,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+    // headers of the last request sent, including cookies and authentication
+    pub request_headers: HeaderMap,
+    // followed before the final response
+    pub redirects: Vec<Redirect>,
+    // size of the status line and headers in bytes
+    pub headers_size: u64,
+    // size of the body in bytes
+    pub size: u64,
+    // file the body was saved to instead of being read into memory
+    pub saved: Option<PathBuf>,
+    pub started: DateTime<Utc>,
+    pub timings: Timings,
+}
+
+pub struct Timings {
+    // following redirects and answering a digest challenge before the last hop
+    pub redirect: Option<Duration>,
+    // DNS lookup, not set when no new connection was made
+    pub dns: Option<Duration>,
+    // TCP connect and TLS handshake after the DNS lookup, the client does both in one step
+    pub connect: Option<Duration>,
+    // time from sending the last hop until its response headers were received
+    pub first_byte: Duration,
+    // time to read the response body
+    pub receive: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.redirect.unwrap_or_default() + self.hop()
+    }
+
+    // the last hop, from sending it until the body was read
+    pub fn hop(&self) -> Duration {
+        self.first_byte + self.receive
+    }
+
+    // time the server took after the request was sent
+    pub fn wait(&self) -> Duration {
+        let connect = self.dns.unwrap_or_default() + self.connect.unwrap_or_default();
+        self.first_byte.saturating_sub(connect)
+    }
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl HttpResponse {
+    pub fn send(
+        request: &HttpRequest,
+        client: &Client,
+        trace: &TraceLog,
+        cookies: Option<&Arc<CookieJar>>,
+        verbose: bool,
+    ) -> anyhow::Result<Self> {
+        let sender = Sender {
+            client,
+            trace,
+            cookies,
+            verbose,
+        };
+        let policy = RetryPolicy::from_request(request)?;
+        let mut attempt = 0;
+        let (response, started, start, received) = loop {
+            // only the last attempt is reported
+            if let Ok(mut trace) = trace.lock() {
+                *trace = Trace::default();
+            }
+            let started = Utc::now();
+            let start = Instant::now();
+            let result = sender.send(request, None)?;
+            let result = authenticate(request, &sender, trace, result)?;
+            let received = Instant::now();
+
+            let Some(delay) = policy.retry_delay(attempt, &result) else {
+                break (result?, started, start, received);
+            };
+            let reason = match &result {
+                Ok(response) => response.status().to_string(),
+                Err(e) if e.is_timeout() => "timeout".to_string(),
+                Err(_) => "connection error".to_string(),
+            };
+            attempt += 1;
+            eprintln!(
+                "Retrying in {} after {} ({} of {})",
+                format_duration(delay),
+                reason,
+                attempt,
+                policy.retries
+            );
+            thread::sleep(delay);
+        };
+
+        let (hop, dns, connect, request_headers, redirects) = match trace.lock() {
+            Ok(mut trace) => (
+                trace.hop,
+                trace.dns,
+                trace
+                    .connect
+                    .map(|connect| connect.saturating_sub(trace.dns.unwrap_or_default())),
+                mem::take(&mut trace.headers),
+                mem::take(&mut trace.redirects),
+            ),
+            Err(_) => (None, None, None, request.headers.clone(), Vec::new()),
+        };
+        let mut response = Self::read(response, request.output.as_ref(), started)?;
+        response.request_headers = request_headers;
+        response.redirects = redirects;
+        response.timings.redirect = hop.map(|hop| hop.saturating_duration_since(start));
+        response.timings.dns = dns;
+        response.timings.connect = connect;
+        response.timings.first_byte = received.saturating_duration_since(hop.unwrap_or(start));
+        Ok(response)
+    }
+
+    fn read(
+        mut response: Response,
+        output: Option<&OutputFile>,
+        started: DateTime<Utc>,
+    ) -> anyhow::Result<Self> {
+        let start = Instant::now();
+        let url = response.url().clone();
+        let version = response.version();
+        let status = response.status();
+        let headers = response.headers().clone();
+
+        // as on the wire in HTTP/1.1, e.g. "HTTP/1.1 200 OK\r\n"
+        let status_line = format!("{:?} {}\r\n", version, status);
+        let headers_size = headers
+            .iter()
+            .fold(status_line.len() + 2, |size, (key, value)| {
+                size + key.as_str().len() + value.len() + 4
+            }) as u64;
+
+        let (body, size, saved) = match output {
+            Some(output) => {
+                let path = output_path(output);
+                let size = save_body(&mut response, &path)?;
+                (String::new(), size, Some(path))
+            }
+            None => {
+                let body = response.text()?;
+                let size = body.len() as u64;
+                (body, size, None)
+            }
+        };
+
+        Ok(HttpResponse {
+            url,
+            version,
+            status,
+            headers,
+            body,
+            request_headers: HeaderMap::new(),
+            redirects: Vec::new(),
+            headers_size,
+            size,
+            saved,
+            started,
+            timings: Timings {
+                redirect: None,
+                dns: None,
+                connect: None,
+                first_byte: Duration::ZERO,
+                receive: start.elapsed(),
+            },
+        })
+    }
+}
+
+// Sends the parsed request with the client, printing it as sent in verbose mode
+struct Sender<'a> {
+    client: &'a Client,
+    trace: &'a TraceLog,
+    cookies: Option<&'a Arc<CookieJar>>,
+    verbose: bool,
+}
+
+impl Sender<'_> {
+    fn send(
+        &self,
+        request: &HttpRequest,
+        authorization: Option<String>,
+    ) -> anyhow::Result<reqwest::Result<Response>> {
+        let mut builder = request.builder(self.client);
+        if let Some(authorization) = authorization {
+            builder = builder.header(AUTHORIZATION, authorization);
+        }
+        let mut built: Request = builder.build()?;
+        // the client sets the same cookies, they are added here to be printed
+        if let Some(cookie) = self.cookies.and_then(|jar| jar.cookies(built.url())) {
+            built.headers_mut().insert(COOKIE, cookie);
+        }
+        // signed last, the signature covers the request as sent
+        if let Some(aws) = &request.aws {
+            aws.sign(&mut built, Utc::now())?;
+        }
+        if let Ok(mut trace) = self.trace.lock() {
+            trace.headers = built.headers().clone();
+            trace.sent = Some(Instant::now());
+        }
+        if self.verbose {
+            output_request(&built);
+        }
+        Ok(self.client.execute(built))
+    }
+}
+
+// Sends the request again answering a digest challenge
+fn authenticate(
+    request: &HttpRequest,
+    sender: &Sender,
+    trace: &TraceLog,
+    result: reqwest::Result<Response>,
+) -> anyhow::Result<reqwest::Result<Response>> {
+    let (Some(digest), Ok(response)) = (&request.digest, &result) else {
+        return Ok(result);
+    };
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(result);
+    }
+    let authorization = digest.authorization(
+        response.headers(),
+        &request.method,
+        &request.url,
+        request.body.as_deref(),
+    )?;
+    let Some(authorization) = authorization else {
+        return Ok(result);
+    };
+    // redirects are followed again, only the ones of the answer are reported
+    if let Ok(mut trace) = trace.lock() {
+        trace.redirects.clear();
+        trace.next_hop();
+    }
+    sender.send(request, Some(authorization))
+}
+
+// An existing file is kept unless overwriting, e.g. report.pdf is saved as report-1.pdf
+fn output_path(output: &OutputFile) -> PathBuf {
+    let path = &output.path;
+    if output.overwrite || !path.exists() {
+        return path.clone();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|index| path.with_file_name(format!("{}-{}{}", stem, index, extension)))
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| path.clone())
+}
+
+// Stream the body to a file as is, without decoding it as text
+fn save_body(response: &mut Response, path: &Path) -> anyhow::Result<u64> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory \"{}\"", parent.display()))?;
+    }
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create file \"{}\"", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let mut progress = Progress::new(response.content_length());
+
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size: u64 = 0;
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer
+            .write_all(&buffer[..read])
+            .with_context(|| format!("Failed to write file \"{}\"", path.display()))?;
+        size += read as u64;
+        progress.update(size);
+    }
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write file \"{}\"", path.display()))?;
+    progress.finish();
+
+    Ok(size)
+}
+
+// Download progress on stderr, shown for large or unknown sizes when it is a terminal
+struct Progress {
+    total: Option<u64>,
+    enabled: bool,
+    last: Instant,
+}
+
+impl Progress {
+    fn new(total: Option<u64>) -> Self {
+        Progress {
+            total,
+            enabled: io::stderr().is_terminal()
+                && total.is_none_or(|total| total > PROGRESS_THRESHOLD),
+            last: Instant::now(),
+        }
+    }
+
+    fn update(&mut self, size: u64) {
+        if !self.enabled || self.last.elapsed() < Duration::from_millis(100) {
+            return;
+        }
+        self.last = Instant::now();
+        match self.total {
+            Some(total) if total > 0 => eprint!(
+                "\r\x1b[KDownloading {} / {} ({}%)",
+                format_size(size),
+                format_size(total),
+                size * 100 / total
+            ),
+            _ => eprint!("\r\x1b[KDownloading {}", format_size(size)),
+        }
+    }
+
+    fn finish(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
@@ -0,0 +1,516 @@
+use anyhow::Context as ErrorContext;
+use chrono::{Duration, Utc};
+use fake::{
+    faker::{
+        internet::raw::{DomainSuffix, IPv4, IPv6, Password, SafeEmail},
+        lorem::raw::{Word, Words},
+    },
+    locales,
+    uuid::UUIDv4,
+    Fake,
+};
+use jsonschema::{Draft, ValidationOptions, Validator};
+use serde_json::{json, Map, Value};
+use std::{fs, path::Path};
+
+use crate::random::regex_sample;
+
+// Attempts at generating a document that is valid against its schema
+const RANDOM_ATTEMPTS: usize = 20;
+
+// Arrays without maxItems get up to this many more items than their minimum
+const RANDOM_ITEMS: u64 = 3;
+
+pub struct SchemaViolation {
+    pub pointer: String,
+    pub message: String,
+}
+
+pub fn load_schema(path: &Path) -> anyhow::Result<Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to open schema \"{}\"", path.display()))?;
+    let schema = serde_json::from_str(&content)
+        .with_context(|| format!("Schema should be valid JSON \"{}\"", path.display()))?;
+    Ok(schema)
+}
+
+pub fn validate_json(schema_path: &Path, body: &str) -> anyhow::Result<Vec<SchemaViolation>> {
+    let schema = load_schema(schema_path)?;
+    let instance: Value =
+        serde_json::from_str(body).context("Response body should be valid JSON")?;
+    let validator = file_validator(schema_path, &schema)?;
+    Ok(collect_violations(&validator, &instance))
+}
+
+// Generate random documents until one is valid against the schema
+pub fn random_json(schema_path: &Path) -> anyhow::Result<String> {
+    let schema = load_schema(schema_path)?;
+    let validator = file_validator(schema_path, &schema)?;
+    let mut violations = Vec::new();
+    for _ in 0..RANDOM_ATTEMPTS {
+        let instance = random_value(&schema, &schema, &mut Vec::new());
+        violations = collect_violations(&validator, &instance);
+        if violations.is_empty() {
+            return Ok(serde_json::to_string_pretty(&instance)?);
+        }
+    }
+    let violation = &violations[0];
+    anyhow::bail!(
+        "Failed to generate a document valid against \"{}\", {} {}",
+        schema_path.display(),
+        violation.pointer,
+        violation.message
+    )
+}
+
+pub fn validate_value(
+    schema: &Value,
+    draft: Draft,
+    instance: &Value,
+) -> anyhow::Result<Vec<SchemaViolation>> {
+    let validator = build_validator(jsonschema::options().with_draft(draft), schema)?;
+    Ok(collect_violations(&validator, instance))
+}
+
+fn file_validator(schema_path: &Path, schema: &Value) -> anyhow::Result<Validator> {
+    // relative references are resolved against the schema file location
+    let base_uri = fs::canonicalize(schema_path)
+        .map(|path| format!("file://{}", path.display()))
+        .unwrap_or_default();
+
+    build_validator(jsonschema::options().with_base_uri(base_uri), schema)
+        .with_context(|| format!("Invalid schema \"{}\"", schema_path.display()))
+}
+
+fn build_validator(options: ValidationOptions, schema: &Value) -> anyhow::Result<Validator> {
+    options
+        .should_validate_formats(true)
+        .build(schema)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+fn collect_violations(validator: &Validator, instance: &Value) -> Vec<SchemaViolation> {
+    validator
+        .iter_errors(instance)
+        .map(|error| SchemaViolation {
+            pointer: format!("#{}", error.instance_path()),
+            message: error.to_string(),
+        })
+        .collect()
+}
+
+pub fn resolve_ref<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    let mut schema = schema;
+    // follow local references such as "#/components/schemas/User",
+    // bounded to guard against reference cycles
+    for _ in 0..32 {
+        let target = schema
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| root.pointer(pointer));
+        match target {
+            Some(target) => schema = target,
+            None => break,
+        }
+    }
+    schema
+}
+
+// Build a JSON body template for a schema, with dynamic variables
+// in place of values that have no example
+pub fn sample_template(root: &Value, schema: &Value) -> String {
+    let mut out = String::new();
+    write_sample(root, schema, 0, &mut Vec::new(), &mut out);
+    out
+}
+
+// Build a template for a single value, e.g. a path or query parameter
+pub fn sample_scalar(root: &Value, schema: &Value) -> String {
+    let schema = resolve_ref(root, schema);
+    if let Some(example) = example_value(schema) {
+        return match example {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+    }
+    match schema_type(schema) {
+        "integer" | "number" => number_helper(schema),
+        "boolean" => "{{$randomBoolean}}".to_string(),
+        _ => string_helper(schema).to_string(),
+    }
+}
+
+fn write_sample(
+    root: &Value,
+    schema: &Value,
+    depth: usize,
+    refs: &mut Vec<String>,
+    out: &mut String,
+) {
+    // recursive schemas are cut short
+    let reference = schema.get("$ref").and_then(Value::as_str);
+    if let Some(reference) = reference {
+        if refs.iter().any(|seen| seen == reference) {
+            out.push_str("null");
+            return;
+        }
+        refs.push(reference.to_string());
+        write_sample(root, resolve_ref(root, schema), depth, refs, out);
+        refs.pop();
+        return;
+    }
+    let indent = "  ".repeat(depth);
+
+    if let Some(example) = example_value(schema) {
+        let pretty = serde_json::to_string_pretty(example).unwrap_or_default();
+        out.push_str(&pretty.replace('\n', &format!("\n{}", indent)));
+        return;
+    }
+
+    if let Some(all_of) = schema["allOf"].as_array() {
+        let mut merged = serde_json::Map::new();
+        let mut required = Vec::new();
+        for part in all_of.iter().map(|part| resolve_ref(root, part)) {
+            if let Some(properties) = part["properties"].as_object() {
+                merged.extend(properties.clone());
+            }
+            if let Some(names) = part["required"].as_array() {
+                required.extend(names.clone());
+            }
+        }
+        let merged = json!({
+            "type": "object",
+            "properties": merged,
+            "required": required,
+        });
+        write_sample(root, &merged, depth, refs, out);
+        return;
+    }
+
+    let variant = schema["oneOf"]
+        .as_array()
+        .or_else(|| schema["anyOf"].as_array())
+        .and_then(|variants| variants.first());
+    if let Some(variant) = variant {
+        write_sample(root, variant, depth, refs, out);
+        return;
+    }
+
+    match schema_type(schema) {
+        "object" => {
+            let required = schema["required"].as_array();
+            // read only properties are left out unless required
+            let properties: Vec<(&String, &Value)> = schema["properties"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(name, property)| {
+                    resolve_ref(root, property)["readOnly"] != Value::Bool(true)
+                        || required.is_some_and(|required| required.contains(&json!(name)))
+                })
+                .collect();
+            if properties.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            out.push_str("{\n");
+            for (index, (name, property)) in properties.into_iter().enumerate() {
+                if index > 0 {
+                    out.push_str(",\n");
+                }
+                out.push_str(&format!("{}  \"{}\": ", indent, name));
+                write_sample(root, property, depth + 1, refs, out);
+            }
+            out.push_str(&format!("\n{}}}", indent));
+        }
+        "array" => {
+            let items = &schema["items"];
+            if is_recursive(items, refs) {
+                out.push_str("[]");
+                return;
+            }
+            out.push_str(&format!("[\n{}  ", indent));
+            write_sample(root, items, depth + 1, refs, out);
+            out.push_str(&format!("\n{}]", indent));
+        }
+        "integer" | "number" => out.push_str(&number_helper(schema)),
+        "boolean" => out.push_str("{{$randomBoolean}}"),
+        "string" => out.push_str(&format!("\"{}\"", string_helper(schema))),
+        _ => out.push_str("null"),
+    }
+}
+
+// Random value for a schema, optional properties are left out at random
+// and recursive references are cut short
+fn random_value(root: &Value, schema: &Value, refs: &mut Vec<String>) -> Value {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        if refs.iter().any(|seen| seen == reference) {
+            return Value::Null;
+        }
+        refs.push(reference.to_string());
+        let value = random_value(root, resolve_ref(root, schema), refs);
+        refs.pop();
+        return value;
+    }
+
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(values) = schema["enum"]
+        .as_array()
+        .filter(|values| !values.is_empty())
+    {
+        return values[(0..values.len()).fake::<usize>()].clone();
+    }
+
+    if let Some(all_of) = schema["allOf"].as_array() {
+        let mut merged = schema.as_object().cloned().unwrap_or_default();
+        merged.remove("allOf");
+        for part in all_of.iter().map(|part| resolve_ref(root, part)) {
+            for (key, value) in part.as_object().into_iter().flatten() {
+                match (merged.get_mut(key), value) {
+                    (Some(Value::Object(merged)), Value::Object(value)) => {
+                        merged.extend(value.clone())
+                    }
+                    (Some(Value::Array(merged)), Value::Array(value)) => {
+                        merged.extend(value.clone())
+                    }
+                    (Some(_), _) => {}
+                    (None, value) => {
+                        merged.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        return random_value(root, &Value::Object(merged), refs);
+    }
+
+    let variants = schema["oneOf"]
+        .as_array()
+        .or_else(|| schema["anyOf"].as_array())
+        .filter(|variants| !variants.is_empty());
+    if let Some(variants) = variants {
+        let variant = &variants[(0..variants.len()).fake::<usize>()];
+        return random_value(root, variant, refs);
+    }
+
+    match schema_type(schema) {
+        "object" => random_object(root, schema, refs),
+        "array" => {
+            let items = &schema["items"];
+            if is_recursive(items, refs) {
+                return json!([]);
+            }
+            let max = schema["maxItems"].as_u64();
+            let min = schema["minItems"]
+                .as_u64()
+                .unwrap_or(1)
+                .min(max.unwrap_or(u64::MAX));
+            let count = (min..=max.unwrap_or(min + RANDOM_ITEMS)).fake::<u64>();
+            let mut values: Vec<Value> = Vec::new();
+            // unique items get a few attempts each
+            for _ in 0..count * 10 {
+                if values.len() as u64 == count {
+                    break;
+                }
+                let value = random_value(root, items, refs);
+                if schema["uniqueItems"] != Value::Bool(true) || !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            Value::Array(values)
+        }
+        "integer" => {
+            let (min, max) = bounds(schema, 1.0);
+            let multiple = schema["multipleOf"]
+                .as_i64()
+                .filter(|multiple| *multiple > 0);
+            let (min, max) = (min.ceil() as i64, max.floor() as i64);
+            match multiple {
+                Some(multiple) => {
+                    // rounded up in i128 as the minimum saturates to i64::MIN
+                    let min = -(-(min as i128)).div_euclid(multiple as i128);
+                    let (min, max) = (min as i64, max.div_euclid(multiple));
+                    json!((min.min(max)..=max).fake::<i64>() * multiple)
+                }
+                None => json!((min.min(max)..=max).fake::<i64>()),
+            }
+        }
+        "number" => {
+            let (min, max) = bounds(schema, 0.01);
+            let value = (min.min(max)..=max).fake::<f64>();
+            // two decimals unless that leaves the range
+            let rounded = (value * 100.0).round() / 100.0;
+            match schema["multipleOf"]
+                .as_f64()
+                .filter(|multiple| *multiple > 0.0)
+            {
+                Some(multiple) => json!((value / multiple).round() * multiple),
+                None if (min..=max).contains(&rounded) => json!(rounded),
+                None => json!(value),
+            }
+        }
+        "boolean" => json!(fake::Faker.fake::<bool>()),
+        "null" => Value::Null,
+        _ => Value::String(random_string(schema)),
+    }
+}
+
+fn random_object(root: &Value, schema: &Value, refs: &mut Vec<String>) -> Value {
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let min = schema["minProperties"].as_u64().unwrap_or(0) as usize;
+
+    // required properties are always set, read only and recursive ones only when
+    // required, others at random or as long as there are fewer than minProperties
+    let mut missing = min.saturating_sub(required.len());
+    let mut object = Map::new();
+    for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+        let is_required = required.contains(&name.as_str());
+        let include = is_required
+            || (resolve_ref(root, property)["readOnly"] != Value::Bool(true)
+                && !is_recursive(property, refs)
+                && (missing > 0 || fake::Faker.fake::<bool>()));
+        if include {
+            if !is_required {
+                missing = missing.saturating_sub(1);
+            }
+            object.insert(name.clone(), random_value(root, property, refs));
+        }
+    }
+    for name in required {
+        if !object.contains_key(name) {
+            object.insert(name.to_string(), json!(Word(locales::EN).fake::<String>()));
+        }
+    }
+    Value::Object(object)
+}
+
+fn random_string(schema: &Value) -> String {
+    if let Some(value) = schema["pattern"]
+        .as_str()
+        .and_then(|pattern| regex_sample(pattern).ok())
+    {
+        return value;
+    }
+    let time = Utc::now() - Duration::seconds((0..365 * 24 * 3600).fake::<i64>());
+    match schema["format"].as_str().unwrap_or_default() {
+        "email" => SafeEmail(locales::EN).fake(),
+        "uuid" => UUIDv4.fake(),
+        "date-time" => time.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        "date" => time.format("%Y-%m-%d").to_string(),
+        "time" => time.format("%H:%M:%SZ").to_string(),
+        "ipv4" => IPv4(locales::EN).fake(),
+        "ipv6" => IPv6(locales::EN).fake(),
+        "hostname" => format!(
+            "{}.{}",
+            Word(locales::EN).fake::<String>(),
+            DomainSuffix(locales::EN).fake::<String>()
+        ),
+        "uri" | "url" => format!("https://example.com/{}", Word(locales::EN).fake::<String>()),
+        "password" => Password(locales::EN, 16..33).fake(),
+        _ => {
+            // words, padded and cut to the length range
+            let min = schema["minLength"].as_u64().unwrap_or(0) as usize;
+            let max = schema["maxLength"].as_u64().map(|max| max as usize);
+            let mut value = Words(locales::EN, 1..4).fake::<Vec<String>>().join(" ");
+            while value.chars().count() < min {
+                value.push(' ');
+                value.push_str(&Word(locales::EN).fake::<String>());
+            }
+            match max {
+                Some(max) => value.chars().take(max).collect(),
+                None => value,
+            }
+        }
+    }
+}
+
+// Inclusive range of a number, exclusive bounds are moved in by step
+fn bounds(schema: &Value, step: f64) -> (f64, f64) {
+    let bound = |inclusive: &str, exclusive: &str, step: f64| match &schema[exclusive] {
+        Value::Number(bound) => bound.as_f64().map(|bound| bound + step),
+        // draft 4 marks the inclusive bound as exclusive
+        Value::Bool(true) => schema[inclusive].as_f64().map(|bound| bound + step),
+        _ => schema[inclusive].as_f64(),
+    };
+    let min = bound("minimum", "exclusiveMinimum", step);
+    let max = bound("maximum", "exclusiveMaximum", -step);
+    match (min, max) {
+        (Some(min), Some(max)) => (min, max),
+        (Some(min), None) => (min, min + 1000.0),
+        (None, Some(max)) if max >= 0.0 => (0.0, max),
+        (None, Some(max)) => (max - 1000.0, max),
+        (None, None) => (0.0, 1000.0),
+    }
+}
+
+fn is_recursive(schema: &Value, refs: &[String]) -> bool {
+    schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .is_some_and(|reference| refs.iter().any(|seen| seen == reference))
+}
+
+fn example_value(schema: &Value) -> Option<&Value> {
+    schema
+        .get("example")
+        .or_else(|| schema.get("const"))
+        .or_else(|| schema.get("default"))
+        .or_else(|| {
+            schema["examples"]
+                .as_array()
+                .and_then(|values| values.first())
+        })
+        .or_else(|| schema["enum"].as_array().and_then(|values| values.first()))
+}
+
+fn schema_type(schema: &Value) -> &str {
+    let kind = match &schema["type"] {
+        Value::String(kind) => Some(kind.as_str()),
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null"),
+        _ => None,
+    };
+    kind.unwrap_or_else(|| {
+        if schema.get("properties").is_some() {
+            "object"
+        } else if schema.get("items").is_some() {
+            "array"
+        } else {
+            ""
+        }
+    })
+}
+
+fn number_helper(schema: &Value) -> String {
+    match (schema["minimum"].as_i64(), schema["maximum"].as_i64()) {
+        (Some(min), Some(max)) => format!("{{{{$randomInt min={} max={}}}}}", min, max),
+        (Some(min), None) => format!("{{{{$randomInt min={} max={}}}}}", min, min + 1000),
+        (None, Some(max)) => format!("{{{{$randomInt min=0 max={}}}}}", max),
+        (None, None) => "{{$randomInt}}".to_string(),
+    }
+}
+
+fn string_helper(schema: &Value) -> &'static str {
+    match schema["format"].as_str().unwrap_or_default() {
+        "email" => "{{$randomSafeEmail}}",
+        "uuid" => "{{$randomUUIDv4}}",
+        "date-time" => "{{$timestamp format='iso8601'}}",
+        "date" => "{{$timestamp format='%Y-%m-%d'}}",
+        "time" => "{{$timestamp format='%H:%M:%S'}}",
+        "ipv4" => "{{$randomIPv4}}",
+        "ipv6" => "{{$randomIPv6}}",
+        "hostname" => "example.{{$randomDomainSuffix}}",
+        "uri" | "url" => "https://example.com/{{$randomWord}}",
+        "password" => "{{$randomPassword}}",
+        _ => "{{$randomWord}}",
+    }
+}
@@ -0,0 +1,207 @@
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD},
+    Engine,
+};
+use handlebars::{
+    Context as VariableContext, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+    RenderError, RenderErrorReason, ScopedJson,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use regex::Regex;
+use serde_json::Value;
+
+// Everything but the unreserved characters A-Z, a-z, 0-9, -, _, . and ~
+const URL_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+// Helper returning a value, so it can be used in subexpressions,
+// e.g. {{$base64 ($concat user ':' pass)}}
+pub struct Transform(pub fn(&Helper) -> Result<Value, RenderError>);
+
+impl HelperDef for Transform {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc VariableContext,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        Ok(ScopedJson::Derived((self.0)(h)?))
+    }
+}
+
+// Helper returning a value that is already escaped, it is not escaped again
+pub struct Encoder(pub fn(&Helper) -> Result<Value, RenderError>);
+
+impl HelperDef for Encoder {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc VariableContext,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        Ok(ScopedJson::Derived((self.0)(h)?))
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc VariableContext,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        out.write(&text(&(self.0)(h)?))?;
+        Ok(())
+    }
+}
+
+// {{$base64 value}}
+pub fn base64(h: &Helper) -> Result<Value, RenderError> {
+    Ok(STANDARD.encode(param(h, 0, "value")?).into())
+}
+
+// {{$base64Decode value}}, standard or URL safe alphabet, padded or not
+pub fn base64_decode(h: &Helper) -> Result<Value, RenderError> {
+    let value = param(h, 0, "value")?;
+    let value = value.trim();
+    let bytes = [STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD]
+        .iter()
+        .find_map(|engine| engine.decode(value).ok())
+        .ok_or_else(|| {
+            RenderErrorReason::Other(format!("$base64Decode got invalid base64 \"{}\"", value))
+        })?;
+    let decoded = String::from_utf8(bytes).map_err(|_| {
+        RenderErrorReason::Other(format!("$base64Decode of \"{}\" is not UTF-8", value))
+    })?;
+    Ok(decoded.into())
+}
+
+// {{$urlEncode value}}, for query parameters and path segments
+pub fn url_encode(h: &Helper) -> Result<Value, RenderError> {
+    Ok(encode_url(&param(h, 0, "value")?).into())
+}
+
+// {{$jsonEscape value}}, to be placed inside a JSON string
+pub fn json_escape(h: &Helper) -> Result<Value, RenderError> {
+    Ok(escape_json(&param(h, 0, "value")?).into())
+}
+
+pub fn encode_url(value: &str) -> String {
+    utf8_percent_encode(value, URL_ENCODE).to_string()
+}
+
+pub fn escape_json(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+pub fn upper(h: &Helper) -> Result<Value, RenderError> {
+    Ok(param(h, 0, "value")?.to_uppercase().into())
+}
+
+pub fn lower(h: &Helper) -> Result<Value, RenderError> {
+    Ok(param(h, 0, "value")?.to_lowercase().into())
+}
+
+pub fn trim(h: &Helper) -> Result<Value, RenderError> {
+    Ok(param(h, 0, "value")?.trim().into())
+}
+
+// {{$substring value start end}}, in characters, negative positions count from the end
+pub fn substring(h: &Helper) -> Result<Value, RenderError> {
+    let value = param(h, 0, "value")?;
+    let chars: Vec<char> = value.chars().collect();
+    let position = |index: usize, name: &str| -> Result<Option<usize>, RenderError> {
+        let Some(param) = h.param(index) else {
+            return Ok(None);
+        };
+        let position = param.value().as_i64().ok_or_else(|| {
+            RenderErrorReason::Other(format!(
+                "$substring {} should be a number, got {}",
+                name,
+                param.value()
+            ))
+        })?;
+        let len = chars.len() as i64;
+        let position = match position < 0 {
+            true => len + position,
+            false => position,
+        };
+        Ok(Some(position.clamp(0, len) as usize))
+    };
+    let start = position(1, "start")?.unwrap_or(0);
+    let end = position(2, "end")?.unwrap_or(chars.len()).max(start);
+    Ok(chars[start..end].iter().collect::<String>().into())
+}
+
+// {{$replace value pattern replacement}}, all matches of the regular expression
+// are replaced, the replacement can refer to groups as $1 or ${name}
+pub fn replace(h: &Helper) -> Result<Value, RenderError> {
+    let value = param(h, 0, "value")?;
+    let pattern = param(h, 1, "pattern")?;
+    let replacement = param(h, 2, "replacement")?;
+    let regex = Regex::new(&pattern).map_err(|e| {
+        RenderErrorReason::Other(format!(
+            "$replace pattern \"{}\" is invalid: {}",
+            pattern, e
+        ))
+    })?;
+    Ok(regex
+        .replace_all(&value, replacement.as_str())
+        .into_owned()
+        .into())
+}
+
+// {{$concat value ...}}
+pub fn concat(h: &Helper) -> Result<Value, RenderError> {
+    let values = (0..h.params().len())
+        .map(|index| param(h, index, "value"))
+        .collect::<Result<String, _>>()?;
+    Ok(values.into())
+}
+
+// {{$default value fallback}}, the fallback when the value is undefined, null or empty
+pub fn default(h: &Helper) -> Result<Value, RenderError> {
+    let value = h
+        .param(0)
+        .filter(|param| !param.is_value_missing())
+        .map(|param| param.value());
+    match value {
+        None | Some(Value::Null) => {}
+        Some(Value::String(value)) if value.is_empty() => {}
+        Some(value) => return Ok(value.clone()),
+    }
+    h.param(1)
+        .filter(|param| !param.is_value_missing())
+        .map(|param| param.value().clone())
+        .ok_or_else(|| {
+            RenderErrorReason::Other("$default is missing the fallback parameter".to_string())
+                .into()
+        })
+}
+
+// Parameters are used as strings, other values as their JSON text
+pub fn param(h: &Helper, index: usize, name: &str) -> Result<String, RenderError> {
+    let param = h.param(index).ok_or_else(|| {
+        RenderErrorReason::Other(format!("{} is missing the {} parameter", h.name(), name))
+    })?;
+    if param.is_value_missing() {
+        return Err(RenderErrorReason::MissingVariable(
+            param.relative_path().map(|path| path.to_string()),
+        )
+        .into());
+    }
+    Ok(text(param.value()))
+}
+
+pub fn text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}